@@ -0,0 +1,20 @@
+use std::path::PathBuf;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR")?);
+
+    // Only the descriptor set is used, the generated types come from the weather_service_rpc crate.
+    tonic_build::configure()
+        .build_client(false)
+        .build_server(false)
+        .file_descriptor_set_path(out_dir.join("weather_service_descriptor.bin"))
+        .compile(&["proto/weather_service.proto"], &["proto"])?;
+
+    tonic_build::configure()
+        .build_client(false)
+        .extern_path(".weather_service", "::weather_service_rpc")
+        .file_descriptor_set_path(out_dir.join("weather_server_descriptor.bin"))
+        .compile(&["proto/weather_server.proto"], &["proto"])?;
+
+    Ok(())
+}
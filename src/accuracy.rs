@@ -0,0 +1,136 @@
+use crate::defs;
+use crate::archive::{Archive, Observation};
+use crate::forecast::WeatherForecaster;
+use crate::forecast::registry::{self, Capability};
+
+use std::sync::Arc;
+use std::time::Duration;
+
+const OBSERVATION_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Number of past days for which the observations are requested. Providers keep only a short history.
+const OBSERVATION_WINDOW_DAYS: i64 = 5;
+
+/// Maximal number of observations requested at once, so the reference provider quota is not exhausted.
+const OBSERVATIONS_PER_CHECK: usize = 50;
+
+/// Accuracy of the forecasts of a provider made 'lead_days' before the date for locations in the region,
+/// see Archive::scores.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Score {
+    pub provider: String,
+    pub lead_days: u32,
+    pub region: String,
+    pub samples: u32,
+    /// Mean absolute error and mean error (forecast minus observed) of the temperatures.
+    pub min_t_mae: f32,
+    pub max_t_mae: f32,
+    pub min_t_bias: f32,
+    pub max_t_bias: f32,
+    /// Share of the forecasts whose condition has the same category as the observed one.
+    pub condition_match_rate: f32,
+}
+
+/// Returns the provider whose history is used as the observed weather.
+/// It is ACCURACY_REFERENCE_PROVIDER or the first enabled provider with the history.
+pub fn reference_provider() -> Option<String> {
+    if let Some(provider) = defs::CONFIG.general_section().get(defs::ACCURACY_REFERENCE_PROVIDER_KEY) {
+        return Some(provider.to_string());
+    }
+
+    registry::enabled_providers().into_iter()
+        .find(|provider| provider.metadata.capabilities.contains(&Capability::History))
+        .map(|provider| provider.name)
+}
+
+/// Reduces the description of the weather to a category, since each provider has its own wording.
+pub(crate) fn condition_category(condition: &str) -> &'static str {
+    let condition = condition.to_lowercase();
+    let categories = [
+        ("storm", &["thunder", "storm"][..]),
+        ("snow", &["snow", "sleet", "blizzard", "ice pellets"][..]),
+        ("rain", &["rain", "drizzle", "shower"][..]),
+        ("fog", &["fog", "mist", "haze"][..]),
+        ("cloudy", &["cloud", "overcast"][..]),
+        ("clear", &["clear", "sun", "fair"][..]),
+    ];
+
+    categories.iter()
+        .find(|(_, keywords)| keywords.iter().any(|keyword| condition.contains(keyword)))
+        .map_or("other", |(category, _)| *category)
+}
+
+/// Requests the observed weather for the archived forecasts whose date has passed.
+async fn observe(archive: &Arc<Archive>, reference: &str) {
+    let forecaster = match WeatherForecaster::new(reference) {
+        Ok(forecaster) => forecaster,
+        Err(err) => {
+            tracing::warn!(provider = reference, error = %err, "Reference provider for the accuracy is not available.");
+            return;
+        }
+    };
+
+    let today = chrono::Utc::today().naive_utc();
+    let since = today - chrono::Duration::days(OBSERVATION_WINDOW_DAYS);
+
+    let pending_archive = archive.clone();
+    let missing = match tokio::task::spawn_blocking(
+        move || pending_archive.missing_observations(since, today, OBSERVATIONS_PER_CHECK)).await {
+        Ok(Ok(missing)) => missing,
+        Ok(Err(err)) => {
+            tracing::error!(error = %err, "Unable to find forecasts without observations.");
+            return;
+        },
+        Err(err) => {
+            tracing::error!(error = %err, "Accuracy observation task has failed.");
+            return;
+        },
+    };
+
+    for (location, date) in missing {
+        let weather = match forecaster.get_weather(location.clone(), date.format("%m.%d.%Y").to_string()).await {
+            Ok(weather) => weather,
+            Err(err) => {
+                tracing::warn!(provider = reference, %date, error = %err, "Unable to get the observed weather.");
+                continue;
+            }
+        };
+
+        let observation = Observation { location, date, weather };
+        let archive = archive.clone();
+        if let Ok(Err(err)) = tokio::task::spawn_blocking(move || archive.store_observation(&observation)).await {
+            tracing::error!(error = %err, "Unable to store the observed weather.");
+        }
+    }
+}
+
+/// Spawns a task that requests the observed weather for the past dates of the archived forecasts every hour.
+pub fn spawn_observer(archive: Arc<Archive>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(OBSERVATION_CHECK_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            match reference_provider() {
+                Some(reference) => observe(&archive, &reference).await,
+                None => tracing::warn!("There is no provider with the history to score the forecasts."),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_condition_category() {
+        assert_eq!(condition_category("Clouds, scattered clouds"), "cloudy");
+        assert_eq!(condition_category("Partly cloudy"), "cloudy");
+        assert_eq!(condition_category("Patchy light rain with thunder"), "storm");
+        assert_eq!(condition_category("Rain, light rain"), "rain");
+        assert_eq!(condition_category("Sunny"), "clear");
+        assert_eq!(condition_category(""), "other");
+    }
+}
@@ -0,0 +1,481 @@
+use crate::accuracy::{self, Score};
+use crate::defs;
+
+use lazy_static::lazy_static;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::{params, Connection};
+use weather_service_rpc::{Location, WeatherForecast};
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Maximal difference in degrees between the coordinates of the same location.
+const COORDINATE_TOLERANCE: f64 = 0.01;
+
+const RETENTION_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+const SECONDS_IN_DAY: i64 = 24 * 60 * 60;
+
+lazy_static! {
+    static ref ARCHIVE: Option<Arc<Archive>> = open_configured();
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub description: String,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.description)
+    }
+}
+
+impl std::error::Error for Error { }
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Error { description: format!("Forecast archive error. {}", err) }
+    }
+}
+
+/// Forecast for a single day as it was fetched from the provider.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArchivedForecast {
+    pub provider: String,
+    pub location: Location,
+    /// Unix time when the forecast was fetched.
+    pub fetched_at: i64,
+    pub forecast: WeatherForecast,
+}
+
+/// Weather observed at the location on the date, used to score the archived forecasts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Observation {
+    pub location: Location,
+    pub date: chrono::NaiveDate,
+    pub weather: WeatherForecast,
+}
+
+fn day_start(date: chrono::NaiveDate) -> i64 {
+    date.and_hms(0, 0, 0).timestamp()
+}
+
+fn to_date(day_start: i64) -> chrono::NaiveDate {
+    chrono::NaiveDateTime::from_timestamp(day_start, 0).date()
+}
+
+/// SQLite database with every forecast fetched by the server.
+pub struct Archive {
+    connection: Mutex<Connection>,
+}
+
+impl Archive {
+    pub fn open(path: &str) -> Result<Self, Error> {
+        Self::init(Connection::open(path)?)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(connection: Connection) -> Result<Self, Error> {
+        connection.create_scalar_function("condition_category", 1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            |context| Ok(accuracy::condition_category(&context.get::<String>(0)?)))?;
+
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS forecasts (
+                provider TEXT NOT NULL,
+                name TEXT NOT NULL,
+                state TEXT NOT NULL,
+                country TEXT NOT NULL,
+                lat REAL NOT NULL,
+                lon REAL NOT NULL,
+                fetched_at INTEGER NOT NULL,
+                dt INTEGER NOT NULL,
+                min_t REAL NOT NULL,
+                max_t REAL NOT NULL,
+                avg_t REAL NOT NULL,
+                condition TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS forecasts_by_date ON forecasts (dt, lat, lon);
+            CREATE INDEX IF NOT EXISTS forecasts_by_fetch_time ON forecasts (fetched_at);
+            CREATE TABLE IF NOT EXISTS observations (
+                lat REAL NOT NULL,
+                lon REAL NOT NULL,
+                date INTEGER NOT NULL,
+                min_t REAL NOT NULL,
+                max_t REAL NOT NULL,
+                avg_t REAL NOT NULL,
+                condition TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS observations_by_date ON observations (date, lat, lon);")?;
+
+        Ok(Self { connection: Mutex::new(connection) })
+    }
+
+    /// Stores the forecasts fetched from the provider for the location at 'fetched_at'.
+    pub fn store(&self, provider: &str, location: &Location, fetched_at: i64, forecasts: &[WeatherForecast])
+        -> Result<(), Error> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+
+        {
+            let mut statement = transaction.prepare_cached(
+                "INSERT INTO forecasts (provider, name, state, country, lat, lon, fetched_at, dt, min_t, max_t, avg_t, condition)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)")?;
+
+            for forecast in forecasts {
+                statement.execute(params![
+                    provider, location.name, location.state, location.country, location.lat, location.lon,
+                    fetched_at, forecast.dt, forecast.min_t, forecast.max_t, forecast.avg_t, forecast.condition])?;
+            }
+        }
+
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Returns the forecasts for the date (in UTC) at the location, the latest fetched first.
+    /// If the provider is specified, only its forecasts are returned.
+    pub fn query(&self, location: &Location, date: chrono::NaiveDate, provider: Option<&str>)
+        -> Result<Vec<ArchivedForecast>, Error> {
+        let day_start = day_start(date);
+
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare_cached(
+            "SELECT provider, name, state, country, lat, lon, fetched_at, dt, min_t, max_t, avg_t, condition
+             FROM forecasts
+             WHERE dt >= ?1 AND dt < ?2 AND ABS(lat - ?3) < ?5 AND ABS(lon - ?4) < ?5 AND (?6 IS NULL OR provider = ?6)
+             ORDER BY fetched_at DESC, provider")?;
+
+        let rows = statement.query_map(
+            params![day_start, day_start + SECONDS_IN_DAY, location.lat, location.lon, COORDINATE_TOLERANCE, provider],
+            |row| {
+                Ok(ArchivedForecast {
+                    provider: row.get(0)?,
+                    location: Location {
+                        name: row.get(1)?,
+                        state: row.get(2)?,
+                        country: row.get(3)?,
+                        lat: row.get(4)?,
+                        lon: row.get(5)?,
+                    },
+                    fetched_at: row.get(6)?,
+                    forecast: WeatherForecast {
+                        dt: row.get(7)?,
+                        min_t: row.get(8)?,
+                        max_t: row.get(9)?,
+                        avg_t: row.get(10)?,
+                        condition: row.get(11)?,
+                    },
+                })
+            })?;
+
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Removes the forecasts fetched before 'fetched_before' and the observations before that time.
+    /// Returns the number of removed forecasts.
+    pub fn purge(&self, fetched_before: i64) -> Result<usize, Error> {
+        let connection = self.connection.lock().unwrap();
+
+        connection.execute("DELETE FROM observations WHERE date < ?1", params![fetched_before])?;
+        Ok(connection.execute("DELETE FROM forecasts WHERE fetched_at < ?1", params![fetched_before])?)
+    }
+
+    pub fn store_observation(&self, observation: &Observation) -> Result<(), Error> {
+        let connection = self.connection.lock().unwrap();
+        let weather = &observation.weather;
+
+        connection.execute(
+            "INSERT INTO observations (lat, lon, date, min_t, max_t, avg_t, condition) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![observation.location.lat, observation.location.lon, day_start(observation.date),
+                    weather.min_t, weather.max_t, weather.avg_t, weather.condition])?;
+
+        Ok(())
+    }
+
+    /// Returns up to 'limit' locations and dates from the range [since, until) that have archived forecasts
+    /// but no observation yet. The weather fetched for past dates doesn't need an observation.
+    pub fn missing_observations(&self, since: chrono::NaiveDate, until: chrono::NaiveDate, limit: usize)
+        -> Result<Vec<(Location, chrono::NaiveDate)>, Error> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare_cached(
+            "SELECT name, state, country, lat, lon, dt - dt % 86400 AS date
+             FROM forecasts AS f
+             WHERE dt >= ?1 AND dt < ?2 AND fetched_at < dt - dt % 86400 + 86400 AND NOT EXISTS (
+                SELECT 1 FROM observations AS o
+                WHERE o.date = f.dt - f.dt % 86400 AND ABS(o.lat - f.lat) < ?3 AND ABS(o.lon - f.lon) < ?3)
+             GROUP BY ROUND(lat, 2), ROUND(lon, 2), date
+             ORDER BY date
+             LIMIT ?4")?;
+
+        let rows = statement.query_map(
+            params![day_start(since), day_start(until), COORDINATE_TOLERANCE, limit as i64],
+            |row| {
+                let location = Location {
+                    name: row.get(0)?,
+                    state: row.get(1)?,
+                    country: row.get(2)?,
+                    lat: row.get(3)?,
+                    lon: row.get(4)?,
+                };
+                Ok((location, to_date(row.get(5)?)))
+            })?;
+
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Calculates the accuracy scores of the archived forecasts that have an observation for their date
+    /// per provider, lead time and region, optionally only for the provider or the region.
+    /// A forecast fetched several times a day for the same location and date is counted once, by the latest fetch,
+    /// so the frequently requested locations don't outweigh the others. The weather fetched for past dates
+    /// is not a forecast, so it is skipped.
+    pub fn scores(&self, provider: Option<&str>, region: Option<&str>) -> Result<Vec<Score>, Error> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare_cached(
+            "WITH latest AS (
+                SELECT provider, country, lat, lon, dt, min_t, max_t, condition, MAX(fetched_at) AS fetched_at
+                FROM forecasts
+                WHERE fetched_at < dt - dt % 86400 + 86400 AND (?2 IS NULL OR provider = ?2)
+                GROUP BY provider, ROUND(lat, 2), ROUND(lon, 2), dt, fetched_at / 86400
+             ),
+             observed AS (
+                SELECT lat, lon, date, min_t, max_t, condition
+                FROM observations
+                GROUP BY ROUND(lat, 2), ROUND(lon, 2), date
+             ),
+             errors AS (
+                SELECT f.provider,
+                       (o.date - (f.fetched_at - f.fetched_at % 86400)) / 86400 AS lead_days,
+                       CASE WHEN f.country = '' THEN 'Unknown' ELSE f.country END AS region,
+                       f.min_t - o.min_t AS min_t_error,
+                       f.max_t - o.max_t AS max_t_error,
+                       condition_category(f.condition) = condition_category(o.condition) AS condition_match
+                FROM latest AS f JOIN observed AS o
+                   ON o.date = f.dt - f.dt % 86400 AND ABS(o.lat - f.lat) < ?1 AND ABS(o.lon - f.lon) < ?1
+             )
+             SELECT provider, lead_days, region, COUNT(*),
+                    AVG(ABS(min_t_error)), AVG(ABS(max_t_error)), AVG(min_t_error), AVG(max_t_error),
+                    AVG(condition_match)
+             FROM errors
+             WHERE ?3 IS NULL OR region = ?3
+             GROUP BY provider, lead_days, region
+             ORDER BY provider, lead_days, region")?;
+
+        let rows = statement.query_map(params![COORDINATE_TOLERANCE, provider, region], |row| {
+            Ok(Score {
+                provider: row.get(0)?,
+                lead_days: row.get(1)?,
+                region: row.get(2)?,
+                samples: row.get(3)?,
+                min_t_mae: row.get(4)?,
+                max_t_mae: row.get(5)?,
+                min_t_bias: row.get(6)?,
+                max_t_bias: row.get(7)?,
+                condition_match_rate: row.get(8)?,
+            })
+        })?;
+
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+}
+
+fn open_configured() -> Option<Arc<Archive>> {
+    let path = defs::CONFIG.general_section().get(defs::ARCHIVE_PATH_KEY).filter(|path| !path.is_empty())?;
+
+    match Archive::open(path) {
+        Ok(archive) => Some(Arc::new(archive)),
+        Err(err) => {
+            tracing::error!(path, error = %err, "Unable to open the forecast archive, forecasts are not archived.");
+            None
+        }
+    }
+}
+
+/// Returns the archive configured by ARCHIVE_PATH. None if the archive is not configured or can't be opened.
+pub fn global() -> Option<Arc<Archive>> {
+    ARCHIVE.clone()
+}
+
+/// Returns the time during which the archived forecasts are kept.
+pub fn retention_period() -> Duration {
+    let days = defs::CONFIG.general_section().get(defs::ARCHIVE_RETENTION_KEY)
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(90);
+
+    Duration::from_secs(days * SECONDS_IN_DAY as u64)
+}
+
+/// Spawns a task that removes the forecasts older than ARCHIVE_RETENTION_DAYS every hour.
+pub fn spawn_retention(archive: Arc<Archive>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(RETENTION_CHECK_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            let fetched_before = chrono::Utc::now().timestamp() - retention_period().as_secs() as i64;
+            let archive = archive.clone();
+            match tokio::task::spawn_blocking(move || archive.purge(fetched_before)).await {
+                Ok(Ok(removed)) => tracing::info!(removed, "Removed expired forecasts from the archive."),
+                Ok(Err(err)) => tracing::error!(error = %err, "Unable to remove expired forecasts from the archive."),
+                Err(err) => tracing::error!(error = %err, "Archive retention task has failed."),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_location(lat: f32) -> Location {
+        Location {
+            name: "Name".to_string(),
+            state: "State".to_string(),
+            country: "Country".to_string(),
+            lon: 1.1,
+            lat,
+        }
+    }
+
+    fn get_forecast(dt: i64, avg_t: f32) -> WeatherForecast {
+        WeatherForecast {
+            dt,
+            min_t: avg_t - 0.5,
+            max_t: avg_t + 0.5,
+            avg_t,
+            condition: "Warm and cool".to_string(),
+        }
+    }
+
+    #[test]
+    pub fn test_store_and_query() {
+        let archive = Archive::open_in_memory().unwrap();
+        let date = chrono::NaiveDate::from_ymd(2000, 1, 2);
+
+        archive.store("First", &get_location(2.2), 100, &[get_forecast(946684800, 20.0), get_forecast(946771200, 21.0)])
+            .unwrap();
+        archive.store("Second", &get_location(2.2), 200, &[get_forecast(946814400, 22.0)]).unwrap();
+        archive.store("First", &get_location(40.0), 300, &[get_forecast(946771200, 23.0)]).unwrap();
+
+        let found = archive.query(&get_location(2.201), date, None).unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].provider, "Second");
+        assert_eq!(found[0].fetched_at, 200);
+        assert_eq!(found[0].forecast, get_forecast(946814400, 22.0));
+        assert_eq!(found[1].provider, "First");
+        assert_eq!(found[1].location, get_location(2.2));
+
+        let found = archive.query(&get_location(2.2), date, Some("First")).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].forecast.avg_t, 21.0);
+
+        assert!(archive.query(&get_location(2.2), chrono::NaiveDate::from_ymd(2000, 1, 5), None).unwrap().is_empty());
+    }
+
+    #[test]
+    pub fn test_observations() {
+        let archive = Archive::open_in_memory().unwrap();
+        let since = chrono::NaiveDate::from_ymd(2000, 1, 1);
+        let until = chrono::NaiveDate::from_ymd(2000, 1, 3);
+
+        archive.store("First", &get_location(2.2), 100, &[get_forecast(946684800, 20.0), get_forecast(946814400, 21.0)])
+            .unwrap();
+        archive.store("Second", &get_location(2.2), 200, &[get_forecast(946771200, 22.0), get_forecast(946857600, 23.0)])
+            .unwrap();
+
+        let missing = archive.missing_observations(since, until, 10).unwrap();
+        assert_eq!(missing, vec![(get_location(2.2), since), (get_location(2.2), since.succ())]);
+        assert_eq!(archive.missing_observations(since, until, 1).unwrap().len(), 1);
+
+        archive.store_observation(&Observation {
+            location: get_location(2.2),
+            date: since.succ(),
+            weather: get_forecast(946771200, 19.0),
+        }).unwrap();
+
+        assert_eq!(archive.missing_observations(since, until, 10).unwrap(), vec![(get_location(2.2), since)]);
+
+        // The weather fetched after the date has passed is neither scored nor observed.
+        archive.store("First", &get_location(2.2), 946771200 + 2 * 86400, &[get_forecast(946771200, 19.0)]).unwrap();
+        archive.store("First", &get_location(3.3), 946684800 + 2 * 86400, &[get_forecast(946684800, 19.0)]).unwrap();
+        assert_eq!(archive.missing_observations(since, until, 10).unwrap(), vec![(get_location(2.2), since)]);
+
+        let scores = archive.scores(None, None).unwrap();
+        assert_eq!(scores.len(), 2);
+        assert_eq!((scores[0].provider.as_str(), scores[0].samples), ("First", 1));
+        assert_eq!(scores[0].min_t_bias, 2.0);
+        assert_eq!((scores[1].provider.as_str(), scores[1].samples), ("Second", 1));
+        assert_eq!(scores[1].max_t_mae, 3.0);
+    }
+
+    #[test]
+    pub fn test_scores() {
+        let archive = Archive::open_in_memory().unwrap();
+        let date = chrono::NaiveDate::from_ymd(2000, 1, 2);
+        let dt = 946771200; // 01.02.2000
+        let forecast = |min_t: f32, max_t: f32, condition: &str| WeatherForecast {
+            dt,
+            min_t,
+            max_t,
+            avg_t: (min_t + max_t) / 2.0,
+            condition: condition.to_string(),
+        };
+        let location = |country: &str| Location { country: country.to_string(), ..get_location(2.2) };
+
+        archive.store("First", &location("GB"), dt, &[forecast(10.0, 20.0, "Sunny")]).unwrap();
+        archive.store("First", &location("GB"), dt - 86400, &[forecast(10.0, 20.0, "Sunny")]).unwrap();
+        archive.store("Second", &location(""), dt, &[forecast(12.0, 19.0, "Sunny")]).unwrap();
+        archive.store("Third", &location("GB"), dt - 86400, &[forecast(14.0, 18.0, "Rain")]).unwrap();
+        // Fetched later on the same day, so only this one is counted.
+        archive.store("Third", &location("GB"), dt - 86400 + 3600, &[forecast(10.0, 20.0, "Sunny")]).unwrap();
+
+        archive.store_observation(&Observation {
+            location: get_location(2.2),
+            date,
+            weather: forecast(12.0, 19.0, "Clear, clear sky"),
+        }).unwrap();
+
+        let scores = archive.scores(None, None).unwrap();
+        assert_eq!(scores.len(), 4);
+
+        assert_eq!(scores[0], Score {
+            provider: "First".to_string(),
+            lead_days: 0,
+            region: "GB".to_string(),
+            samples: 1,
+            min_t_mae: 2.0,
+            max_t_mae: 1.0,
+            min_t_bias: -2.0,
+            max_t_bias: 1.0,
+            condition_match_rate: 1.0,
+        });
+        assert_eq!(scores[1].lead_days, 1);
+        assert_eq!(scores[2].region, "Unknown");
+        assert_eq!(scores[2].min_t_mae, 0.0);
+        assert_eq!((scores[3].provider.as_str(), scores[3].samples, scores[3].min_t_bias), ("Third", 1, -2.0));
+
+        assert_eq!(archive.scores(Some("Second"), None).unwrap().len(), 1);
+        assert_eq!(archive.scores(None, Some("GB")).unwrap().len(), 3);
+    }
+
+    #[test]
+    pub fn test_purge() {
+        let archive = Archive::open_in_memory().unwrap();
+        let date = chrono::NaiveDate::from_ymd(2000, 1, 1);
+
+        archive.store("Old", &get_location(2.2), 100, &[get_forecast(946684800, 20.0)]).unwrap();
+        archive.store("New", &get_location(2.2), 200, &[get_forecast(946684800, 20.0)]).unwrap();
+
+        assert_eq!(archive.purge(150).unwrap(), 1);
+
+        let found = archive.query(&get_location(2.2), date, None).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].provider, "New");
+    }
+}
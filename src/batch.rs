@@ -0,0 +1,75 @@
+use crate::defs;
+
+use futures::StreamExt;
+
+use std::future::Future;
+
+/// Limits of the batch RPCs from the configuration file.
+pub struct Limits {
+    /// Maximal number of queries in a request.
+    pub max_queries: usize,
+    /// Number of queries handled at the same time.
+    pub concurrency: usize,
+    /// Maximal number of queries in a geocoding request.
+    /// It is lower, since the queries missing from the cache are sent at GEOCODING_REQUESTS_PER_MINUTE.
+    pub max_geocoding_queries: usize,
+}
+
+impl Limits {
+    pub fn from_config() -> Self {
+        let section = defs::CONFIG.general_section();
+
+        Limits {
+            max_queries: section.get(defs::BATCH_MAX_QUERIES_KEY)
+                .and_then(|value| value.parse::<usize>().ok())
+                .unwrap_or(500),
+            concurrency: section.get(defs::BATCH_CONCURRENCY_KEY)
+                .and_then(|value| value.parse::<usize>().ok())
+                .filter(|value| *value > 0)
+                .unwrap_or(16),
+            max_geocoding_queries: section.get(defs::GEOCODING_BATCH_MAX_QUERIES_KEY)
+                .and_then(|value| value.parse::<usize>().ok())
+                .unwrap_or(200),
+        }
+    }
+}
+
+/// Handles the queries with at most 'concurrency' of them in progress at the same time.
+/// Returns the results in the order of the queries.
+pub async fn run<Q, F, Fut>(queries: Vec<Q>, concurrency: usize, handle: F) -> Vec<Fut::Output>
+    where F: Fn(Q) -> Fut,
+          Fut: Future {
+    futures::stream::iter(queries).map(handle).buffered(concurrency).collect().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    pub async fn test_concurrency_is_bounded() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+
+        let results = run((0..20u64).collect(), 4, |query| {
+            let running = running.clone();
+            let max_running = max_running.clone();
+
+            async move {
+                let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running.fetch_max(now_running, Ordering::SeqCst);
+
+                // Later queries finish first, the results keep the order anyway.
+                tokio::time::sleep(Duration::from_millis(20 - query)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                query * 2
+            }
+        }).await;
+
+        assert_eq!(results, (0..20u64).map(|query| query * 2).collect::<Vec<_>>());
+        assert_eq!(max_running.load(Ordering::SeqCst), 4);
+    }
+}
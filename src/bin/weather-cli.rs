@@ -0,0 +1,312 @@
+//! Command-line client of the WeatherServer. It lists the providers, searches for locations
+//! and fetches the forecasts for a place name, which is handy for scripting and smoke testing a deployment.
+
+#[path = "../json.rs"]
+mod json;
+
+use json::{forecast_to_json, location_to_json};
+use serde_json::{json, Value};
+use tonic::transport::{Certificate, Channel, ClientTlsConfig};
+use weather_service_rpc::weather_service_client::WeatherServiceClient;
+use weather_service_rpc::{Location, LocationSearchParams, WeatherQueryParams};
+
+static USAGE: &str = "\
+Usage: weather-cli [--server URL] [--ca-cert PATH] [--json] COMMAND
+
+Commands:
+    providers                                   List the enabled forecast providers.
+    locations QUERY                             Search for locations, e.g. 'London,GB'.
+    forecast PLACE [--provider NAME] [--date mm.dd.yyyy]
+                                                Fetch the forecast for the first location found for PLACE
+                                                from the provider or from every enabled provider. Default date is today in UTC.
+
+Options:
+    --server URL      Address of the server. Default is http://[::1]:50051.
+    --ca-cert PATH    PEM-encoded CA certificate of the server for https URLs.
+    --json            Print JSON instead of a table.
+
+The exit status is 1 if a request has failed, including the forecast of any provider.";
+
+static DEFAULT_SERVER: &str = "http://[::1]:50051";
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Help,
+    Providers,
+    Locations { query: String },
+    Forecast { place: String, provider: Option<String>, date: Option<String> },
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    server: String,
+    ca_cert: Option<String>,
+    json: bool,
+    command: Command,
+}
+
+/// Parses the arguments without the program name. The words after the command are joined with spaces,
+/// so the place names don't have to be quoted.
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut args = args.into_iter();
+    let mut server = DEFAULT_SERVER.to_string();
+    let (mut ca_cert, mut provider, mut date) = (None, None, None);
+    let mut json = false;
+    let mut words = Vec::new();
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("The {} option requires a value", name));
+
+        match arg.as_str() {
+            "--server" => server = value("--server")?,
+            "--ca-cert" => ca_cert = Some(value("--ca-cert")?),
+            "--provider" => provider = Some(value("--provider")?),
+            "--date" => date = Some(value("--date")?),
+            "--json" => json = true,
+            "-h" | "--help" => words.insert(0, "help".to_string()),
+            option if option.starts_with("--") => return Err(format!("Unknown option {}", option)),
+            _ => words.push(arg),
+        }
+    }
+
+    let rest = words.get(1..).unwrap_or_default().join(" ");
+    let command = match words.first().map(String::as_str) {
+        Some("help") => Command::Help,
+        Some("providers") => Command::Providers,
+        Some("locations") if !rest.is_empty() => Command::Locations { query: rest },
+        Some("forecast") if !rest.is_empty() => Command::Forecast { place: rest, provider, date },
+        Some(command @ ("locations" | "forecast")) => return Err(format!("The {} command requires a place", command)),
+        Some(command) => return Err(format!("Unknown command {}", command)),
+        None => return Err("The command is not specified".to_string()),
+    };
+
+    Ok(Options { server, ca_cert, json, command })
+}
+
+async fn connect(server: &str, ca_cert: Option<&str>) -> Result<WeatherServiceClient<Channel>, String> {
+    let mut endpoint = Channel::from_shared(server.to_string()).map_err(|err| format!("Invalid server URL. {}", err))?;
+
+    if let Some(path) = ca_cert {
+        let pem = std::fs::read(path).map_err(|err| format!("Unable to read {}. {}", path, err))?;
+        endpoint = endpoint.tls_config(ClientTlsConfig::new().ca_certificate(Certificate::from_pem(pem)))
+            .map_err(|err| format!("Invalid TLS configuration. {}", err))?;
+    }
+
+    let channel = endpoint.connect().await.map_err(|err| format!("Unable to connect to {}. {}", server, err))?;
+    Ok(WeatherServiceClient::new(channel))
+}
+
+fn describe_status(status: &tonic::Status) -> String {
+    format!("{:?}: {}", status.code(), status.message())
+}
+
+fn describe_location(location: &Location) -> String {
+    [&location.name, &location.state, &location.country].iter()
+        .filter(|part| !part.is_empty())
+        .map(|part| part.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Formats the rows as columns aligned to the widest cell.
+fn format_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_row = |cells: Vec<&str>| {
+        cells.iter().zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    std::iter::once(format_row(headers.to_vec()))
+        .chain(rows.iter().map(|row| format_row(row.iter().map(String::as_str).collect())))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+async fn list_providers(client: &mut WeatherServiceClient<Channel>, json: bool) -> Result<bool, String> {
+    let providers = client.get_weather_providers(()).await.map_err(|status| describe_status(&status))?
+        .into_inner().providers;
+
+    if json {
+        println!("{}", json!({ "providers": providers }));
+    } else {
+        providers.iter().for_each(|provider| println!("{}", provider));
+    }
+    Ok(true)
+}
+
+async fn search_locations(client: &mut WeatherServiceClient<Channel>, query: String, json: bool) -> Result<bool, String> {
+    let locations = client.get_locations(LocationSearchParams { query }).await
+        .map_err(|status| describe_status(&status))?
+        .into_inner().locations;
+
+    if json {
+        println!("{}", json!({ "locations": locations.iter().map(location_to_json).collect::<Vec<_>>() }));
+    } else {
+        let rows: Vec<Vec<String>> = locations.iter()
+            .map(|location| vec![describe_location(location), location.lat.to_string(), location.lon.to_string()])
+            .collect();
+        println!("{}", format_table(&["LOCATION", "LAT", "LON"], &rows));
+    }
+    Ok(true)
+}
+
+/// Resolves the place through 'GetLocations' and requests its forecast from every provider.
+/// Returns false if any provider has failed.
+async fn fetch_forecasts(client: &mut WeatherServiceClient<Channel>, place: String, provider: Option<String>,
+                         date: Option<String>, json: bool) -> Result<bool, String> {
+    let location = client.get_locations(LocationSearchParams { query: place.clone() }).await
+        .map_err(|status| describe_status(&status))?
+        .into_inner().locations.into_iter().next()
+        .ok_or_else(|| format!("No locations found for '{}'", place))?;
+
+    let providers = match provider {
+        Some(provider) => vec![provider],
+        None => client.get_weather_providers(()).await.map_err(|status| describe_status(&status))?.into_inner().providers,
+    };
+    let date = date.unwrap_or_else(|| chrono::Utc::now().format("%m.%d.%Y").to_string());
+
+    let mut results = Vec::new();
+    for provider in providers {
+        let query = WeatherQueryParams { provider: provider.clone(), location: Some(location.clone()), date: date.clone() };
+        results.push((provider, client.get_weather(query).await.map(|reply| reply.into_inner())));
+    }
+
+    if json {
+        let forecasts: Vec<Value> = results.iter().map(|(provider, result)| match result {
+            Ok(forecast) => json!({ "provider": provider, "forecast": forecast_to_json(forecast) }),
+            Err(status) => json!({
+                "provider": provider,
+                "error": { "code": format!("{:?}", status.code()), "message": status.message() },
+            }),
+        }).collect();
+
+        println!("{}", json!({ "location": location_to_json(&location), "date": date, "forecasts": forecasts }));
+    } else {
+        println!("{} ({}, {}) on {}\n", describe_location(&location), location.lat, location.lon, date);
+
+        let rows: Vec<Vec<String>> = results.iter().map(|(provider, result)| match result {
+            Ok(forecast) => vec![
+                provider.clone(),
+                format!("{:.1}", forecast.min_t),
+                format!("{:.1}", forecast.max_t),
+                format!("{:.1}", forecast.avg_t),
+                forecast.condition.clone(),
+            ],
+            Err(status) => vec![provider.clone(), "-".to_string(), "-".to_string(), "-".to_string(), describe_status(status)],
+        }).collect();
+        println!("{}", format_table(&["PROVIDER", "MIN", "MAX", "AVG", "CONDITION"], &rows));
+    }
+
+    Ok(results.iter().all(|(_, result)| result.is_ok()))
+}
+
+async fn run(options: Options) -> Result<bool, String> {
+    if options.command == Command::Help {
+        println!("{}", USAGE);
+        return Ok(true);
+    }
+
+    let mut client = connect(&options.server, options.ca_cert.as_deref()).await?;
+
+    match options.command {
+        Command::Help => Ok(true),
+        Command::Providers => list_providers(&mut client, options.json).await,
+        Command::Locations { query } => search_locations(&mut client, query, options.json).await,
+        Command::Forecast { place, provider, date } =>
+            fetch_forecasts(&mut client, place, provider, date, options.json).await,
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    match run(options).await {
+        Ok(true) => {},
+        Ok(false) => std::process::exit(1),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    pub fn test_parse_args() {
+        assert_eq!(parse(&["providers"]), Ok(Options {
+            server: DEFAULT_SERVER.to_string(),
+            ca_cert: None,
+            json: false,
+            command: Command::Providers,
+        }));
+
+        assert_eq!(parse(&["--json", "forecast", "New", "York", "--provider", "OpenMeteo", "--server", "http://host:1"]),
+            Ok(Options {
+                server: "http://host:1".to_string(),
+                ca_cert: None,
+                json: true,
+                command: Command::Forecast {
+                    place: "New York".to_string(),
+                    provider: Some("OpenMeteo".to_string()),
+                    date: None,
+                },
+            }));
+
+        assert_eq!(parse(&["locations", "--help"]).unwrap().command, Command::Help);
+        assert!(parse(&["forecast"]).is_err());
+        assert!(parse(&["forecast", "London", "--date"]).is_err());
+        assert!(parse(&["--verbose", "providers"]).is_err());
+        assert!(parse(&["weather"]).is_err());
+        assert!(parse(&[]).is_err());
+    }
+
+    #[test]
+    pub fn test_format_table() {
+        let rows = vec![
+            vec!["OpenMeteo".to_string(), "12.5".to_string(), "Rain".to_string()],
+            vec!["NWS".to_string(), "-".to_string(), "Unavailable: NWS is unavailable.".to_string()],
+        ];
+
+        assert_eq!(format_table(&["PROVIDER", "AVG", "CONDITION"], &rows), "\
+PROVIDER   AVG   CONDITION
+OpenMeteo  12.5  Rain
+NWS        -     Unavailable: NWS is unavailable.");
+    }
+
+    #[test]
+    pub fn test_describe_location() {
+        let location = Location {
+            name: "London".to_string(),
+            state: String::new(),
+            country: "GB".to_string(),
+            lat: 51.5,
+            lon: -0.12,
+        };
+
+        assert_eq!(describe_location(&location), "London, GB");
+    }
+}
@@ -0,0 +1,106 @@
+use futures::future::BoxFuture;
+use futures::FutureExt;
+
+use std::any::Any;
+use std::panic::AssertUnwindSafe;
+use std::task::{Context, Poll};
+
+use tonic::body::BoxBody;
+use tower::{Layer, Service};
+
+/// Layer that converts a panic in any RPC handler into a response with the 'Internal' status.
+/// Without it the panic unwinds through the connection task and drops the whole connection,
+/// including the other requests multiplexed over it.
+#[derive(Clone, Default)]
+pub struct CatchPanicLayer;
+
+impl<S> Layer<S> for CatchPanicLayer {
+    type Service = CatchPanic<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CatchPanic { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct CatchPanic<S> {
+    inner: S,
+}
+
+fn panic_message(panic: &Box<dyn Any + Send>) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        return message;
+    }
+    if let Some(message) = panic.downcast_ref::<String>() {
+        return message.as_str();
+    }
+    return "unknown panic";
+}
+
+fn panic_response(path: &str, panic: Box<dyn Any + Send>) -> http::Response<BoxBody> {
+    tracing::error!(path, panic = panic_message(&panic), "RPC handler has panicked.");
+
+    tonic::Status::internal("An internal error occured when handling the request.").to_http()
+}
+
+impl<S, ReqBody> Service<http::Request<ReqBody>> for CatchPanic<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<BoxBody>>,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<ReqBody>) -> Self::Future {
+        let path = request.uri().path().to_string();
+
+        let inner = &mut self.inner;
+        let future = match std::panic::catch_unwind(AssertUnwindSafe(|| inner.call(request))) {
+            Ok(future) => future,
+            Err(panic) => return futures::future::ready(Ok(panic_response(&path, panic))).boxed(),
+        };
+
+        async move {
+            match AssertUnwindSafe(future).catch_unwind().await {
+                Ok(result) => result,
+                Err(panic) => Ok(panic_response(&path, panic)),
+            }
+        }.boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+
+    async fn panicking_handler(_: http::Request<()>) -> Result<http::Response<BoxBody>, Infallible> {
+        panic!("Handler has failed.");
+    }
+
+    #[tokio::test]
+    pub async fn test_panic_is_converted_to_internal() {
+        let mut service = CatchPanicLayer.layer(tower::service_fn(panicking_handler));
+
+        let response = service.call(http::Request::new(())).await.unwrap();
+        let status = tonic::Status::from_header_map(response.headers()).unwrap();
+        assert_eq!(status.code(), tonic::Code::Internal);
+    }
+
+    #[tokio::test]
+    pub async fn test_response_is_passed_through() {
+        let mut service = CatchPanicLayer.layer(tower::service_fn(|_: http::Request<()>| async {
+            Ok::<_, Infallible>(tonic::Status::not_found("test").to_http())
+        }));
+
+        let response = service.call(http::Request::new(())).await.unwrap();
+        let status = tonic::Status::from_header_map(response.headers()).unwrap();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+    }
+}
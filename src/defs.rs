@@ -3,6 +3,55 @@ use lazy_static::lazy_static;
 
 pub static WEATHER_SERVER_ADDR_KEY: &str = "WEATHER_SERVER_ADDRESS";
 
+pub static TLS_CERT_PATH_KEY: &str = "TLS_CERT_PATH";
+pub static TLS_KEY_PATH_KEY: &str = "TLS_KEY_PATH";
+pub static TLS_CLIENT_CA_PATH_KEY: &str = "TLS_CLIENT_CA_PATH";
+pub static TLS_RELOAD_INTERVAL_KEY: &str = "TLS_RELOAD_INTERVAL_SECS";
+pub static TLS_HANDSHAKE_TIMEOUT_KEY: &str = "TLS_HANDSHAKE_TIMEOUT_SECS";
+
+pub static GRPC_WEB_ALLOWED_ORIGINS_KEY: &str = "GRPC_WEB_ALLOWED_ORIGINS";
+
+pub static SHUTDOWN_DRAIN_PERIOD_KEY: &str = "SHUTDOWN_DRAIN_PERIOD_SECS";
+
+pub static HEALTH_CHECK_INTERVAL_KEY: &str = "HEALTH_CHECK_INTERVAL_SECS";
+pub static HEALTH_CHECK_TIMEOUT_KEY: &str = "HEALTH_CHECK_TIMEOUT_SECS";
+
+pub static METRICS_ADDR_KEY: &str = "METRICS_ADDRESS";
+pub static GATEWAY_ADDR_KEY: &str = "GATEWAY_ADDRESS";
+pub static UPSTREAM_MAX_RETRIES_KEY: &str = "UPSTREAM_MAX_RETRIES";
+pub static UPSTREAM_TIMEOUT_KEY: &str = "UPSTREAM_TIMEOUT_SECS";
+
+pub static BATCH_MAX_QUERIES_KEY: &str = "BATCH_MAX_QUERIES";
+pub static BATCH_CONCURRENCY_KEY: &str = "BATCH_CONCURRENCY";
+
+pub static FORECAST_CACHE_TTL_KEY: &str = "FORECAST_CACHE_TTL_SECS";
+pub static PREFETCH_LOCATIONS_KEY: &str = "PREFETCH_LOCATIONS";
+pub static PREFETCH_TOP_LOCATIONS_KEY: &str = "PREFETCH_TOP_LOCATIONS";
+pub static PREFETCH_REQUESTS_PER_MINUTE_KEY: &str = "PREFETCH_REQUESTS_PER_MINUTE";
+
+pub static GEOCODING_CACHE_TTL_KEY: &str = "GEOCODING_CACHE_TTL_SECS";
+pub static GEOCODING_NEGATIVE_CACHE_TTL_KEY: &str = "GEOCODING_NEGATIVE_CACHE_TTL_SECS";
+pub static GEOCODING_CACHE_PATH_KEY: &str = "GEOCODING_CACHE_PATH";
+pub static GEOCODING_REQUESTS_PER_MINUTE_KEY: &str = "GEOCODING_REQUESTS_PER_MINUTE";
+pub static GEOCODING_BATCH_MAX_QUERIES_KEY: &str = "GEOCODING_BATCH_MAX_QUERIES";
+
+pub static ARCHIVE_PATH_KEY: &str = "ARCHIVE_PATH";
+pub static ARCHIVE_RETENTION_KEY: &str = "ARCHIVE_RETENTION_DAYS";
+pub static SAVED_LOCATIONS_PATH_KEY: &str = "SAVED_LOCATIONS_PATH";
+pub static NOTIFICATION_CHECK_INTERVAL_KEY: &str = "NOTIFICATION_CHECK_INTERVAL_SECS";
+pub static NOTIFICATION_WEBHOOK_RETRIES_KEY: &str = "NOTIFICATION_WEBHOOK_RETRIES";
+pub static NOTIFICATION_WEBHOOK_ALLOWED_HOSTS_KEY: &str = "NOTIFICATION_WEBHOOK_ALLOWED_HOSTS";
+pub static SUBSCRIPTION_POLL_INTERVAL_KEY: &str = "SUBSCRIPTION_POLL_INTERVAL_SECS";
+pub static ACCURACY_REFERENCE_PROVIDER_KEY: &str = "ACCURACY_REFERENCE_PROVIDER";
+
+pub static LOG_LEVEL_KEY: &str = "LOG_LEVEL";
+pub static LOG_FORMAT_KEY: &str = "LOG_FORMAT";
+
 lazy_static! {
-    pub static ref CONFIG: Ini = Ini::load_from_file(".weather_server_config").unwrap();
+    // Without the file every field has its default value, which is enough to use the providers without API keys.
+    pub static ref CONFIG: Ini = match Ini::load_from_file(".weather_server_config") {
+        Ok(config) => config,
+        Err(ini::Error::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => Ini::new(),
+        Err(err) => panic!("Unable to load .weather_server_config. {}", err),
+    };
 }
@@ -0,0 +1,315 @@
+use crate::upstream;
+
+use prost::Message;
+use std::collections::HashMap;
+use std::time::Duration;
+use tonic::Code;
+
+/// Domain reported in google.rpc.ErrorInfo details.
+static ERROR_DOMAIN: &str = "weatherserver";
+
+/// Maximum length of the provider message that is passed to the client.
+const MAX_PROVIDER_MESSAGE_LEN: usize = 200;
+
+/// Error returned by forecast and location requests.
+/// Every variant maps to its own gRPC code and carries details that are sent to the client
+/// as google.rpc.ErrorInfo and google.rpc.RetryInfo messages.
+#[derive(Clone, Debug)]
+pub enum Error {
+    /// Client specified an invalid argument, e.g. a malformed date or an unknown provider.
+    InvalidArgument { description: String },
+
+    /// Provider has no forecast for the requested date.
+    DateOutOfRange { provider: String, date: String },
+
+    /// Provider doesn't know the requested location.
+    NotFound { provider: String, description: String },
+
+    /// Provider has rejected the configured API key.
+    Unauthenticated { provider: String, upstream_status: u16 },
+
+    /// The API key has exceeded the quota or the rate limit of the provider.
+    QuotaExceeded { provider: String, upstream_status: u16, retry_after: Option<Duration> },
+
+    /// Provider can't be reached or responds with server errors.
+    ProviderUnavailable { provider: String, upstream_status: Option<u16>, retry_after: Option<Duration> },
+
+    /// Provider didn't respond in time.
+    DeadlineExceeded { provider: String },
+
+    /// Response of the provider can't be processed or another unexpected error has occurred.
+    Internal { provider: Option<String>, description: String },
+}
+
+impl Error {
+    pub fn code(&self) -> Code {
+        match self {
+            Error::InvalidArgument { .. } => Code::InvalidArgument,
+            Error::DateOutOfRange { .. } => Code::OutOfRange,
+            Error::NotFound { .. } => Code::NotFound,
+            Error::Unauthenticated { .. } => Code::Unauthenticated,
+            Error::QuotaExceeded { .. } => Code::ResourceExhausted,
+            Error::ProviderUnavailable { .. } => Code::Unavailable,
+            Error::DeadlineExceeded { .. } => Code::DeadlineExceeded,
+            Error::Internal { .. } => Code::Internal,
+        }
+    }
+
+    /// Machine-readable reason of the error, sent in google.rpc.ErrorInfo.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Error::InvalidArgument { .. } => "INVALID_ARGUMENT",
+            Error::DateOutOfRange { .. } => "DATE_OUT_OF_RANGE",
+            Error::NotFound { .. } => "LOCATION_NOT_FOUND",
+            Error::Unauthenticated { .. } => "INVALID_API_KEY",
+            Error::QuotaExceeded { .. } => "QUOTA_EXCEEDED",
+            Error::ProviderUnavailable { .. } => "PROVIDER_UNAVAILABLE",
+            Error::DeadlineExceeded { .. } => "PROVIDER_TIMEOUT",
+            Error::Internal { .. } => "INTERNAL",
+        }
+    }
+
+    pub fn provider(&self) -> Option<&str> {
+        match self {
+            Error::InvalidArgument { .. } => None,
+            Error::DateOutOfRange { provider, .. } |
+            Error::NotFound { provider, .. } |
+            Error::Unauthenticated { provider, .. } |
+            Error::QuotaExceeded { provider, .. } |
+            Error::ProviderUnavailable { provider, .. } |
+            Error::DeadlineExceeded { provider } => Some(provider),
+            Error::Internal { provider, .. } => provider.as_deref(),
+        }
+    }
+
+    pub fn upstream_status(&self) -> Option<u16> {
+        match self {
+            Error::Unauthenticated { upstream_status, .. } |
+            Error::QuotaExceeded { upstream_status, .. } => Some(*upstream_status),
+            Error::ProviderUnavailable { upstream_status, .. } => *upstream_status,
+            _ => None,
+        }
+    }
+
+    /// Time after which the client may retry the request, if the provider has reported it.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Error::QuotaExceeded { retry_after, .. } |
+            Error::ProviderUnavailable { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// Converts an error that occurred while sending the request to the provider.
+    pub fn from_upstream(provider: &str, err: upstream::Error) -> Self {
+        match err {
+            upstream::Error::CircuitOpen(_) => Error::ProviderUnavailable {
+                provider: provider.to_string(),
+                upstream_status: None,
+                retry_after: Some(upstream::OPEN_DURATION),
+            },
+            upstream::Error::Request(err) if err.is_timeout() => Error::DeadlineExceeded {
+                provider: provider.to_string()
+            },
+            upstream::Error::Request(err) => Error::ProviderUnavailable {
+                provider: provider.to_string(),
+                upstream_status: err.status().map(|status| status.as_u16()),
+                retry_after: None,
+            },
+        }
+    }
+
+    /// Classifies an unsuccessful response of the provider by its status.
+    /// Only the message extracted from the body is kept, the body itself is never passed to the client.
+    pub fn from_status(provider: &str, status: reqwest::StatusCode, retry_after: Option<Duration>, body: &str) -> Self {
+        let provider = provider.to_string();
+        let upstream_status = status.as_u16();
+
+        match status {
+            reqwest::StatusCode::BAD_REQUEST => Error::InvalidArgument {
+                description: format!("{} has rejected the request: {}", provider, provider_message(body))
+            },
+            reqwest::StatusCode::NOT_FOUND => Error::NotFound { provider, description: provider_message(body) },
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN =>
+                Error::Unauthenticated { provider, upstream_status },
+            reqwest::StatusCode::TOO_MANY_REQUESTS =>
+                Error::QuotaExceeded { provider, upstream_status, retry_after },
+            status if status.is_server_error() =>
+                Error::ProviderUnavailable { provider, upstream_status: Some(upstream_status), retry_after },
+            _ => Error::Internal {
+                description: format!("Unexpected response status {} from {}", upstream_status, provider),
+                provider: Some(provider),
+            },
+        }
+    }
+
+    /// Reads the body of an unsuccessful response and classifies it with 'from_status'.
+    pub async fn from_response(provider: &str, response: reqwest::Response) -> Self {
+        let status = response.status();
+        let retry_after = retry_after(response.headers());
+        let body = response.text().await.unwrap_or_default();
+
+        tracing::warn!(provider, %status, body = body.as_str(), "Provider has returned an error.");
+
+        Self::from_status(provider, status, retry_after, &body)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::InvalidArgument { description } => write!(f, "{}", description),
+            Error::DateOutOfRange { provider, date } => write!(f,
+                "{} has no forecast or history for {}. Make sure that you use the format mm.dd.yyyy and the date is within the provider's range",
+                provider, date),
+            Error::NotFound { provider, description } => write!(f, "{} can't find the location: {}", provider, description),
+            Error::Unauthenticated { provider, .. } => write!(f, "{} has rejected the configured API key", provider),
+            Error::QuotaExceeded { provider, .. } => write!(f, "The API key has exceeded the quota of {}", provider),
+            Error::ProviderUnavailable { provider, .. } => write!(f, "{} is unavailable", provider),
+            Error::DeadlineExceeded { provider } => write!(f, "{} didn't respond in time", provider),
+            Error::Internal { description, .. } => write!(f, "{}", description),
+        }
+    }
+}
+
+impl std::error::Error for Error { }
+
+impl From<Error> for tonic::Status {
+    fn from(err: Error) -> Self {
+        let message = format!("{}.", err);
+
+        let mut metadata = HashMap::new();
+        if let Some(provider) = err.provider() {
+            metadata.insert("provider".to_string(), provider.to_string());
+        }
+        if let Some(upstream_status) = err.upstream_status() {
+            metadata.insert("upstream_status".to_string(), upstream_status.to_string());
+        }
+
+        let mut details = vec![pack("google.rpc.ErrorInfo", &details::ErrorInfo {
+            reason: err.reason().to_string(),
+            domain: ERROR_DOMAIN.to_string(),
+            metadata,
+        })];
+
+        if let Some(retry_after) = err.retry_after() {
+            details.push(pack("google.rpc.RetryInfo", &details::RetryInfo {
+                retry_delay: Some(prost_types::Duration { seconds: retry_after.as_secs() as i64, nanos: 0 }),
+            }));
+        }
+
+        let status = details::Status { code: err.code() as i32, message: message.clone(), details };
+
+        tonic::Status::with_details(err.code(), message, status.encode_to_vec().into())
+    }
+}
+
+fn pack(type_name: &str, message: &impl Message) -> prost_types::Any {
+    prost_types::Any {
+        type_url: format!("type.googleapis.com/{}", type_name),
+        value: message.encode_to_vec(),
+    }
+}
+
+/// Parses the 'Retry-After' header. Only the delay in seconds is supported.
+pub fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    headers.get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+/// Extracts a human-readable message from the error body of the provider.
+/// Both {"message": "..."} and {"error": {"message": "..."}} forms are supported.
+pub fn provider_message(body: &str) -> String {
+    let message = serde_json::from_str::<serde_json::Value>(body).ok().and_then(|json| {
+        return json.get("message").or_else(|| json.get("error").and_then(|error| error.get("message")))
+            .and_then(|message| message.as_str())
+            .map(|message| message.to_string());
+    });
+
+    match message {
+        Some(message) => message.chars().take(MAX_PROVIDER_MESSAGE_LEN).collect(),
+        None => "no details provided".to_string(),
+    }
+}
+
+/// Messages from google/rpc/status.proto and google/rpc/error_details.proto.
+pub mod details {
+    use std::collections::HashMap;
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Status {
+        #[prost(int32, tag = "1")]
+        pub code: i32,
+        #[prost(string, tag = "2")]
+        pub message: String,
+        #[prost(message, repeated, tag = "3")]
+        pub details: Vec<prost_types::Any>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ErrorInfo {
+        #[prost(string, tag = "1")]
+        pub reason: String,
+        #[prost(string, tag = "2")]
+        pub domain: String,
+        #[prost(map = "string, string", tag = "3")]
+        pub metadata: HashMap<String, String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct RetryInfo {
+        #[prost(message, optional, tag = "1")]
+        pub retry_delay: Option<prost_types::Duration>,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_status_classification() {
+        let classify = |status: u16| Error::from_status("Test", reqwest::StatusCode::from_u16(status).unwrap(), None, "");
+
+        assert_eq!(classify(400).code(), Code::InvalidArgument);
+        assert_eq!(classify(401).code(), Code::Unauthenticated);
+        assert_eq!(classify(403).code(), Code::Unauthenticated);
+        assert_eq!(classify(404).code(), Code::NotFound);
+        assert_eq!(classify(429).code(), Code::ResourceExhausted);
+        assert_eq!(classify(503).code(), Code::Unavailable);
+        assert_eq!(classify(302).code(), Code::Internal);
+    }
+
+    #[test]
+    pub fn test_provider_message() {
+        assert_eq!(provider_message(r#"{"cod": "400", "message": "wrong latitude"}"#), "wrong latitude");
+        assert_eq!(provider_message(r#"{"error": {"code": 1006, "message": "No location found."}}"#), "No location found.");
+        assert_eq!(provider_message("<html>Bad gateway</html>"), "no details provided");
+        assert_eq!(provider_message(&format!(r#"{{"message": "{}"}}"#, "a".repeat(1000))).len(), MAX_PROVIDER_MESSAGE_LEN);
+    }
+
+    #[test]
+    pub fn test_status_details() {
+        let err = Error::QuotaExceeded {
+            provider: "Test".to_string(),
+            upstream_status: 429,
+            retry_after: Some(Duration::from_secs(60)),
+        };
+        let status = tonic::Status::from(err);
+        assert_eq!(status.code(), Code::ResourceExhausted);
+
+        let decoded = details::Status::decode(status.details()).unwrap();
+        assert_eq!(decoded.details.len(), 2);
+
+        let error_info = details::ErrorInfo::decode(&*decoded.details[0].value).unwrap();
+        assert_eq!(decoded.details[0].type_url, "type.googleapis.com/google.rpc.ErrorInfo");
+        assert_eq!(error_info.reason, "QUOTA_EXCEEDED");
+        assert_eq!(error_info.metadata["provider"], "Test");
+        assert_eq!(error_info.metadata["upstream_status"], "429");
+
+        let retry_info = details::RetryInfo::decode(&*decoded.details[1].value).unwrap();
+        assert_eq!(retry_info.retry_delay.unwrap().seconds, 60);
+    }
+}
@@ -0,0 +1,612 @@
+use super::accuracy;
+use super::archive;
+use super::batch;
+use super::location_search;
+use super::notifications;
+use super::saved_locations;
+use super::subscriptions;
+use super::telemetry;
+
+use super::error::Error;
+use super::forecast::WeatherForecaster;
+use super::forecast::registry::{self, ProviderInfo};
+use super::weather_server_rpc::weather_server_extensions_server::WeatherServerExtensions;
+use super::weather_server_rpc::{AccuracyQuery, AccuracyReport, AccuracyScore};
+use super::weather_server_rpc::{ArchiveQuery, ArchivedForecast, ArchivedForecasts};
+use super::weather_server_rpc::{Capability, ProviderDetails, ProviderDetailsList};
+use super::weather_server_rpc::{CreateSavedLocationRequest, RenameSavedLocationRequest, SavedLocation, SavedLocationId};
+use super::weather_server_rpc::{SavedLocationWeatherQuery, SavedLocations};
+use super::weather_server_rpc::{NotificationRule, NotificationRuleId, NotificationRules, RuleField, RuleOperator};
+use super::weather_server_rpc::{ItemError, WeatherBatchItem, WeatherBatchReply, WeatherBatchRequest};
+use super::weather_server_rpc::weather_batch_item;
+use super::weather_server_rpc::{GeocodeBatchRequest, GeocodeMatch, GeocodeResult};
+use super::weather_server_rpc::geocode_result;
+
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+use weather_service_rpc::{Location, WeatherForecast, WeatherQueryParams};
+
+impl From<registry::Capability> for Capability {
+    fn from(capability: registry::Capability) -> Self {
+        match capability {
+            registry::Capability::Daily => Capability::Daily,
+            registry::Capability::Hourly => Capability::Hourly,
+            registry::Capability::Current => Capability::Current,
+            registry::Capability::Alerts => Capability::Alerts,
+            registry::Capability::History => Capability::History,
+        }
+    }
+}
+
+impl From<ProviderInfo> for ProviderDetails {
+    fn from(provider: ProviderInfo) -> Self {
+        ProviderDetails {
+            name: provider.name,
+            capabilities: provider.metadata.capabilities.into_iter()
+                .map(|capability| Capability::from(capability) as i32).collect(),
+            max_forecast_days: provider.metadata.max_forecast_days,
+            units: provider.metadata.units,
+            languages: provider.metadata.languages,
+            requires_key: provider.metadata.authorization_key.is_some(),
+            key_configured: provider.key_configured,
+        }
+    }
+}
+
+impl From<archive::ArchivedForecast> for ArchivedForecast {
+    fn from(archived: archive::ArchivedForecast) -> Self {
+        ArchivedForecast {
+            provider: archived.provider,
+            location: Some(archived.location),
+            fetched_at: archived.fetched_at,
+            forecast: Some(archived.forecast),
+        }
+    }
+}
+
+impl From<accuracy::Score> for AccuracyScore {
+    fn from(score: accuracy::Score) -> Self {
+        AccuracyScore {
+            provider: score.provider,
+            lead_days: score.lead_days,
+            region: score.region,
+            samples: score.samples,
+            min_t_mae: score.min_t_mae,
+            max_t_mae: score.max_t_mae,
+            min_t_bias: score.min_t_bias,
+            max_t_bias: score.max_t_bias,
+            condition_match_rate: score.condition_match_rate,
+        }
+    }
+}
+
+impl From<saved_locations::SavedLocation> for SavedLocation {
+    fn from(saved: saved_locations::SavedLocation) -> Self {
+        SavedLocation { id: saved.id, name: saved.name, location: Some(saved.location) }
+    }
+}
+
+impl From<notifications::Rule> for NotificationRule {
+    fn from(rule: notifications::Rule) -> Self {
+        let field = match rule.field {
+            notifications::Field::MinT => RuleField::MinT,
+            notifications::Field::MaxT => RuleField::MaxT,
+            notifications::Field::AvgT => RuleField::AvgT,
+            notifications::Field::Condition => RuleField::Condition,
+        };
+        let operator = match rule.operator {
+            notifications::Operator::LessThan => RuleOperator::LessThan,
+            notifications::Operator::GreaterThan => RuleOperator::GreaterThan,
+            notifications::Operator::Matches => RuleOperator::Matches,
+        };
+
+        NotificationRule {
+            id: rule.id,
+            saved_location_id: rule.saved_location_id,
+            provider: rule.provider,
+            field: field as i32,
+            operator: operator as i32,
+            threshold: rule.threshold,
+            text: rule.text,
+            webhook_url: rule.webhook_url,
+            lookahead_days: rule.lookahead_days,
+        }
+    }
+}
+
+/// Converts the rule received from the client. Returns 'InvalidArgument' if the field or the operator is not specified.
+fn to_rule(rule: NotificationRule) -> Result<notifications::Rule, Status> {
+    let field = match RuleField::from_i32(rule.field) {
+        Some(RuleField::MinT) => notifications::Field::MinT,
+        Some(RuleField::MaxT) => notifications::Field::MaxT,
+        Some(RuleField::AvgT) => notifications::Field::AvgT,
+        Some(RuleField::Condition) => notifications::Field::Condition,
+        _ => return Err(make_invalid_argument("Field is not specified")),
+    };
+    let operator = match RuleOperator::from_i32(rule.operator) {
+        Some(RuleOperator::LessThan) => notifications::Operator::LessThan,
+        Some(RuleOperator::GreaterThan) => notifications::Operator::GreaterThan,
+        Some(RuleOperator::Matches) => notifications::Operator::Matches,
+        _ => return Err(make_invalid_argument("Operator is not specified")),
+    };
+
+    Ok(notifications::Rule {
+        id: String::new(),
+        saved_location_id: rule.saved_location_id,
+        provider: rule.provider,
+        field,
+        operator,
+        threshold: rule.threshold,
+        text: rule.text,
+        webhook_url: rule.webhook_url,
+        lookahead_days: if rule.lookahead_days == 0 { notifications::DEFAULT_LOOKAHEAD_DAYS } else { rule.lookahead_days },
+    })
+}
+
+fn make_invalid_argument(description: &str) -> Status {
+    Status::from(Error::InvalidArgument { description: description.to_string() })
+}
+
+fn make_saved_location_not_found(id: &str) -> Status {
+    Status::not_found(format!("There is no saved location with id '{}'", id))
+}
+
+async fn fetch_weather(provider: &str, location: Location, date: String) -> Result<WeatherForecast, Status> {
+    let weather_forecaster = WeatherForecaster::new(provider)?;
+
+    Ok(weather_forecaster.get_weather(location, date).await?)
+}
+
+impl From<Result<WeatherForecast, Status>> for WeatherBatchItem {
+    fn from(result: Result<WeatherForecast, Status>) -> Self {
+        let result = match result {
+            Ok(forecast) => weather_batch_item::Result::Forecast(forecast),
+            Err(status) => weather_batch_item::Result::Error(to_item_error(status)),
+        };
+
+        WeatherBatchItem { result: Some(result) }
+    }
+}
+
+fn to_item_error(status: Status) -> ItemError {
+    ItemError { code: status.code() as i32, message: status.message().to_string(), details: status.details().to_vec() }
+}
+
+/// Returns the best location for the query or 'NotFound' if the geoservice has no locations for it.
+async fn handle_geocode_query(query: String) -> GeocodeResult {
+    let result = match location_search::geocode(&query).await {
+        Ok(Some(found)) => geocode_result::Result::Match(GeocodeMatch {
+            location: Some(found.location),
+            confidence: found.confidence,
+        }),
+        Ok(None) => geocode_result::Result::Error(
+            to_item_error(Status::not_found(format!("No locations found for '{}'", query)))),
+        Err(err) => geocode_result::Result::Error(to_item_error(Status::from(err))),
+    };
+
+    GeocodeResult { query, result: Some(result) }
+}
+
+/// Returns the forecast for the query like 'GetWeather' does.
+async fn handle_weather_query(query: WeatherQueryParams) -> Result<WeatherForecast, Status> {
+    let location = query.location.ok_or_else(|| make_invalid_argument("Location is not specified"))?;
+
+    fetch_weather(&query.provider, location, query.date).await
+}
+
+/// Returns the client identity found by saved_locations::client_id and the storage of the saved locations.
+/// Returns 'Unauthenticated' if there is no client identity and 'Unavailable' if the saved locations are disabled.
+fn saved_locations_of(client: Option<String>) -> Result<(String, Arc<saved_locations::SavedLocations>), Status> {
+    let client = client.ok_or_else(|| Status::unauthenticated(format!(
+        "Client identity is not specified by a client certificate or in the '{}' metadata",
+        saved_locations::CLIENT_ID_HEADER)))?;
+    let storage = saved_locations::global().ok_or_else(|| Status::unavailable("Saved locations are disabled"))?;
+
+    Ok((client, storage))
+}
+
+/// Runs the blocking operation with the saved locations on the blocking thread pool.
+async fn with_saved_locations<T: Send + 'static>(
+    storage: Arc<saved_locations::SavedLocations>,
+    operation: impl FnOnce(&saved_locations::SavedLocations) -> Result<T, saved_locations::Error> + Send + 'static)
+    -> Result<T, Status> {
+    saved_locations::run_blocking(storage, operation).await.or_else(|err| Err(Status::internal(err.description)))
+}
+
+/// An implementation of the WeatherServerExtensions service, which contains the RPCs
+/// that are not part of the WeatherService interface from the weather_service_rpc crate.
+pub struct WeatherServerExtensionsImpl;
+#[tonic::async_trait]
+impl WeatherServerExtensions for WeatherServerExtensionsImpl {
+    type SubscribeWeatherStream = Pin<Box<dyn Stream<Item = Result<WeatherForecast, Status>> + Send>>;
+    type GeocodeBatchStream = Pin<Box<dyn Stream<Item = Result<GeocodeResult, Status>> + Send>>;
+
+    /// Returns the details of the enabled weather forecasting services in the same order as 'GetWeatherProviders'.
+    async fn get_provider_details(&self, request: Request<()>) -> Result<Response<ProviderDetailsList>, Status> {
+        telemetry::handle_rpc("GetProviderDetails", request.metadata(), async {
+            let reply = ProviderDetailsList {
+                providers: registry::enabled_providers().into_iter().map(ProviderDetails::from).collect(),
+            };
+
+            Ok(Response::new(reply))
+        }).await
+    }
+
+    /// Accepts an 'ArchiveQuery' with the location, date-string with format mm.dd.yyyy and optional provider.
+    /// Returns 'InvalidArgument' if the location is not specified or the date has invalid format
+    /// and 'Unavailable' if the archive is disabled.
+    async fn query_archive(&self, query: Request<ArchiveQuery>) -> Result<Response<ArchivedForecasts>, Status> {
+        let metadata = query.metadata().clone();
+
+        telemetry::handle_rpc("QueryArchive", &metadata, async {
+            let params = query.into_inner();
+
+            let location = params.location.ok_or_else(|| make_invalid_argument("Location is not specified"))?;
+            let date = chrono::NaiveDate::parse_from_str(&params.date, "%m.%d.%Y").or_else(
+                |_| Err(make_invalid_argument("Invalid date. Make sure that you use the format mm.dd.yyyy")))?;
+            let provider = if params.provider.is_empty() { None } else { Some(params.provider) };
+
+            let archive = archive::global().ok_or_else(|| Status::unavailable("Forecast archive is disabled"))?;
+            let found = tokio::task::spawn_blocking(move || archive.query(&location, date, provider.as_deref())).await
+                .or_else(|err| Err(Status::internal(err.to_string())))?
+                .or_else(|err| Err(Status::internal(err.description)))?;
+
+            Ok(Response::new(ArchivedForecasts { forecasts: found.into_iter().map(ArchivedForecast::from).collect() }))
+        }).await
+    }
+
+    /// Accepts an 'AccuracyQuery' with optional provider and region and returns the accuracy scores
+    /// per provider, lead time and region. Returns 'Unavailable' if the archive is disabled.
+    async fn get_accuracy(&self, query: Request<AccuracyQuery>) -> Result<Response<AccuracyReport>, Status> {
+        let metadata = query.metadata().clone();
+
+        telemetry::handle_rpc("GetAccuracy", &metadata, async {
+            let params = query.into_inner();
+
+            let archive = archive::global().ok_or_else(|| Status::unavailable("Forecast archive is disabled"))?;
+            let scores = tokio::task::spawn_blocking(move || {
+                let provider = if params.provider.is_empty() { None } else { Some(params.provider.as_str()) };
+                let region = if params.region.is_empty() { None } else { Some(params.region.as_str()) };
+
+                archive.scores(provider, region)
+            }).await
+                .or_else(|err| Err(Status::internal(err.to_string())))?
+                .or_else(|err| Err(Status::internal(err.description)))?;
+
+            Ok(Response::new(AccuracyReport {
+                reference_provider: accuracy::reference_provider().unwrap_or_default(),
+                scores: scores.into_iter().map(AccuracyScore::from).collect(),
+            }))
+        }).await
+    }
+
+    /// Saves the location under the name for the client. Returns 'InvalidArgument' if the name or the location is missing.
+    async fn create_saved_location(&self, request: Request<CreateSavedLocationRequest>)
+        -> Result<Response<SavedLocation>, Status> {
+        let metadata = request.metadata().clone();
+        let client = saved_locations::client_id(&request);
+
+        telemetry::handle_rpc("CreateSavedLocation", &metadata, async {
+            let (client, storage) = saved_locations_of(client)?;
+            let params = request.into_inner();
+
+            saved_locations::validate_name(&params.name).or_else(|err| Err(make_invalid_argument(&err)))?;
+            let location = params.location.ok_or_else(|| make_invalid_argument("Location is not specified"))?;
+
+            let saved = with_saved_locations(storage, move |storage| storage.create(&client, &params.name, location)).await?;
+            Ok(Response::new(SavedLocation::from(saved)))
+        }).await
+    }
+
+    async fn list_saved_locations(&self, request: Request<()>) -> Result<Response<SavedLocations>, Status> {
+        let metadata = request.metadata().clone();
+        let client = saved_locations::client_id(&request);
+
+        telemetry::handle_rpc("ListSavedLocations", &metadata, async {
+            let (client, storage) = saved_locations_of(client)?;
+
+            let saved = with_saved_locations(storage, move |storage| storage.list(&client)).await?;
+            Ok(Response::new(SavedLocations { locations: saved.into_iter().map(SavedLocation::from).collect() }))
+        }).await
+    }
+
+    /// Returns 'NotFound' if the client has no location with the id.
+    async fn rename_saved_location(&self, request: Request<RenameSavedLocationRequest>)
+        -> Result<Response<SavedLocation>, Status> {
+        let metadata = request.metadata().clone();
+        let client = saved_locations::client_id(&request);
+
+        telemetry::handle_rpc("RenameSavedLocation", &metadata, async {
+            let (client, storage) = saved_locations_of(client)?;
+            let params = request.into_inner();
+
+            saved_locations::validate_name(&params.name).or_else(|err| Err(make_invalid_argument(&err)))?;
+
+            let id = params.id.clone();
+            match with_saved_locations(storage, move |storage| storage.rename(&client, &id, &params.name)).await? {
+                Some(saved) => Ok(Response::new(SavedLocation::from(saved))),
+                None => Err(make_saved_location_not_found(&params.id)),
+            }
+        }).await
+    }
+
+    /// Returns 'NotFound' if the client has no location with the id.
+    async fn delete_saved_location(&self, request: Request<SavedLocationId>) -> Result<Response<()>, Status> {
+        let metadata = request.metadata().clone();
+        let client = saved_locations::client_id(&request);
+
+        telemetry::handle_rpc("DeleteSavedLocation", &metadata, async {
+            let (client, storage) = saved_locations_of(client)?;
+            let id = request.into_inner().id;
+
+            let removed_id = id.clone();
+            if with_saved_locations(storage, move |storage| storage.delete(&client, &removed_id)).await? {
+                Ok(Response::new(()))
+            } else {
+                Err(make_saved_location_not_found(&id))
+            }
+        }).await
+    }
+
+    /// Accepts a 'SavedLocationWeatherQuery' with the id of a saved location instead of the 'Location' struct.
+    /// Returns 'NotFound' if the client has no location with the id, other errors are the same as of 'GetWeather'.
+    async fn get_saved_location_weather(&self, query: Request<SavedLocationWeatherQuery>)
+        -> Result<Response<WeatherForecast>, Status> {
+        let metadata = query.metadata().clone();
+        let client = saved_locations::client_id(&query);
+
+        telemetry::handle_rpc("GetSavedLocationWeather", &metadata, async {
+            let (client, storage) = saved_locations_of(client)?;
+            let params = query.into_inner();
+
+            let span = tracing::Span::current();
+            span.record("provider", &params.provider.as_str());
+
+            let weather_forecaster = WeatherForecaster::new(&params.provider).or_else(|err| Err(Status::from(err)))?;
+
+            let id = params.id.clone();
+            let saved = with_saved_locations(storage, move |storage| storage.get(&client, &id)).await?
+                .ok_or_else(|| make_saved_location_not_found(&params.id))?;
+            span.record("location", &format!("{},{}", saved.location.lat, saved.location.lon).as_str());
+
+            match weather_forecaster.get_weather(saved.location, params.date).await {
+                Ok(weather) => Ok(Response::new(weather)),
+                Err(err) => Err(Status::from(err))
+            }
+        }).await
+    }
+
+    /// Accepts the same 'WeatherQueryParams' as 'GetWeather'. Invalid arguments are reported before the stream starts,
+    /// a failure of the first forecast request ends the stream with the same status as 'GetWeather' returns.
+    async fn subscribe_weather(&self, query: Request<WeatherQueryParams>)
+        -> Result<Response<Self::SubscribeWeatherStream>, Status> {
+        let metadata = query.metadata().clone();
+
+        telemetry::handle_rpc("SubscribeWeather", &metadata, async {
+            let params = query.into_inner();
+
+            WeatherForecaster::new(&params.provider).or_else(|err| Err(Status::from(err)))?;
+            let location = params.location.ok_or_else(|| make_invalid_argument("Location is not specified"))?;
+            chrono::NaiveDate::parse_from_str(&params.date, "%m.%d.%Y").or_else(
+                |_| Err(make_invalid_argument("Invalid date. Make sure that you use the format mm.dd.yyyy")))?;
+
+            let span = tracing::Span::current();
+            span.record("provider", &params.provider.as_str());
+            span.record("location", &format!("{},{}", location.lat, location.lon).as_str());
+
+            let key = subscriptions::Key::new(&params.provider, &location, &params.date);
+            let stream = subscriptions::subscribe(key, move || {
+                let provider = params.provider.clone();
+                let location = location.clone();
+                let date = params.date.clone();
+
+                async move { fetch_weather(&provider, location, date).await }
+            });
+
+            Ok(Response::new(Box::pin(stream) as Self::SubscribeWeatherStream))
+        }).await
+    }
+
+    /// Handles every query like 'GetWeather' with at most BATCH_CONCURRENCY queries in progress at the same time.
+    /// Returns 'InvalidArgument' if there are more than BATCH_MAX_QUERIES queries, other errors are returned per item.
+    async fn get_weather_batch(&self, request: Request<WeatherBatchRequest>) -> Result<Response<WeatherBatchReply>, Status> {
+        let metadata = request.metadata().clone();
+
+        telemetry::handle_rpc("GetWeatherBatch", &metadata, async {
+            let queries = request.into_inner().queries;
+
+            let limits = batch::Limits::from_config();
+            if queries.len() > limits.max_queries {
+                return Err(make_invalid_argument(
+                    &format!("Too many queries: {}, the limit is {}", queries.len(), limits.max_queries)));
+            }
+
+            let results = batch::run(queries, limits.concurrency, handle_weather_query).await;
+            Ok(Response::new(WeatherBatchReply { items: results.into_iter().map(WeatherBatchItem::from).collect() }))
+        }).await
+    }
+
+    /// Geocodes every query like 'GetLocations' with at most BATCH_CONCURRENCY queries in progress at the same time.
+    /// Returns 'InvalidArgument' if there are more than GEOCODING_BATCH_MAX_QUERIES queries,
+    /// other errors are returned per result.
+    async fn geocode_batch(&self, request: Request<GeocodeBatchRequest>)
+        -> Result<Response<Self::GeocodeBatchStream>, Status> {
+        let metadata = request.metadata().clone();
+
+        telemetry::handle_rpc("GeocodeBatch", &metadata, async {
+            let queries = request.into_inner().queries;
+
+            let limits = batch::Limits::from_config();
+            if queries.len() > limits.max_geocoding_queries {
+                return Err(make_invalid_argument(
+                    &format!("Too many queries: {}, the limit is {}", queries.len(), limits.max_geocoding_queries)));
+            }
+
+            let stream = futures::stream::iter(queries).map(handle_geocode_query).buffered(limits.concurrency).map(Ok);
+            Ok(Response::new(Box::pin(stream) as Self::GeocodeBatchStream))
+        }).await
+    }
+
+    /// Returns 'InvalidArgument' if the rule is incomplete, the operator doesn't fit the field or the provider
+    /// is not enabled and 'NotFound' if the client has no saved location with the id.
+    async fn create_notification_rule(&self, request: Request<NotificationRule>)
+        -> Result<Response<NotificationRule>, Status> {
+        let metadata = request.metadata().clone();
+        let client = saved_locations::client_id(&request);
+
+        telemetry::handle_rpc("CreateNotificationRule", &metadata, async {
+            let (client, storage) = saved_locations_of(client)?;
+
+            let rule = to_rule(request.into_inner())?;
+            rule.validate().or_else(|err| Err(make_invalid_argument(&err)))?;
+            notifications::Webhook::from_config().resolve(&rule.webhook_url).await
+                .or_else(|err| Err(make_invalid_argument(&err)))?;
+            WeatherForecaster::new(&rule.provider).or_else(|err| Err(Status::from(err)))?;
+
+            let saved_location_id = rule.saved_location_id.clone();
+            let created = with_saved_locations(storage, move |storage| {
+                match storage.get(&client, &rule.saved_location_id)? {
+                    Some(_) => storage.create_rule(&client, rule).map(Some),
+                    None => Ok(None),
+                }
+            }).await?;
+
+            match created {
+                Some(rule) => Ok(Response::new(NotificationRule::from(rule))),
+                None => Err(make_saved_location_not_found(&saved_location_id)),
+            }
+        }).await
+    }
+
+    async fn list_notification_rules(&self, request: Request<()>) -> Result<Response<NotificationRules>, Status> {
+        let metadata = request.metadata().clone();
+        let client = saved_locations::client_id(&request);
+
+        telemetry::handle_rpc("ListNotificationRules", &metadata, async {
+            let (client, storage) = saved_locations_of(client)?;
+
+            let rules = with_saved_locations(storage, move |storage| storage.list_rules(&client)).await?;
+            Ok(Response::new(NotificationRules { rules: rules.into_iter().map(NotificationRule::from).collect() }))
+        }).await
+    }
+
+    /// Returns 'NotFound' if the client has no rule with the id.
+    async fn delete_notification_rule(&self, request: Request<NotificationRuleId>) -> Result<Response<()>, Status> {
+        let metadata = request.metadata().clone();
+        let client = saved_locations::client_id(&request);
+
+        telemetry::handle_rpc("DeleteNotificationRule", &metadata, async {
+            let (client, storage) = saved_locations_of(client)?;
+            let id = request.into_inner().id;
+
+            let removed_id = id.clone();
+            if with_saved_locations(storage, move |storage| storage.delete_rule(&client, &removed_id)).await? {
+                Ok(Response::new(()))
+            } else {
+                Err(Status::not_found(format!("There is no notification rule with id '{}'", id)))
+            }
+        }).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::weather_server_rpc;
+    use super::super::weather_server_rpc::weather_server_extensions_server::WeatherServerExtensionsServer;
+    use prost::Message;
+    use tonic::transport::NamedService;
+
+    #[test]
+    pub fn test_descriptor_matches_service_name() {
+        let descriptor_set = prost_types::FileDescriptorSet::decode(weather_server_rpc::FILE_DESCRIPTOR_SET).unwrap();
+
+        let service_names: Vec<String> = descriptor_set.file.iter().flat_map(|file| {
+            return file.service.iter().map(move |service| {
+                format!("{}.{}", file.package(), service.name())
+            });
+        }).collect();
+
+        assert!(service_names.contains(
+            &WeatherServerExtensionsServer::<WeatherServerExtensionsImpl>::NAME.to_string()));
+    }
+
+    #[tokio::test]
+    pub async fn test_get_provider_details() {
+        let reply = WeatherServerExtensionsImpl.get_provider_details(tonic::Request::new(())).await;
+        let providers = reply.unwrap().into_inner().providers;
+
+        let open_meteo = providers.iter().find(|provider| provider.name == "OpenMeteo").unwrap();
+        assert!(!open_meteo.requires_key);
+        assert_eq!(open_meteo.capabilities, vec![Capability::Daily as i32]);
+        assert_eq!(open_meteo.units, vec!["metric"]);
+
+        let open_weather_map = providers.iter().find(|provider| provider.name == "OpenWeatherMap").unwrap();
+        assert_eq!(open_weather_map.capabilities, vec![Capability::Daily as i32, Capability::History as i32]);
+
+        assert!(providers.iter().all(|provider| !provider.requires_key || provider.key_configured));
+    }
+
+    #[tokio::test]
+    pub async fn test_query_archive_invalid_arguments() {
+        let no_location = WeatherServerExtensionsImpl.query_archive(tonic::Request::new(ArchiveQuery {
+            location: None,
+            date: "01.01.2000".to_string(),
+            provider: String::new(),
+        })).await;
+        assert_eq!(no_location.err().unwrap().code(), tonic::Code::InvalidArgument);
+
+        let wrong_date = WeatherServerExtensionsImpl.query_archive(tonic::Request::new(ArchiveQuery {
+            location: Some(weather_service_rpc::Location {
+                name: "Name".to_string(),
+                state: "State".to_string(),
+                country: "Country".to_string(),
+                lon: 1.1,
+                lat: 2.2,
+            }),
+            date: "2000.01.01".to_string(),
+            provider: String::new(),
+        })).await;
+        assert_eq!(wrong_date.err().unwrap().code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    pub async fn test_weather_batch_item_errors() {
+        let query = |provider: &str, location: Option<weather_service_rpc::Location>| WeatherQueryParams {
+            provider: provider.to_string(),
+            location,
+            date: "01.01.2000".to_string(),
+        };
+        let location = weather_service_rpc::Location {
+            name: "Name".to_string(),
+            state: "State".to_string(),
+            country: "Country".to_string(),
+            lon: 1.1,
+            lat: 2.2,
+        };
+
+        let reply = WeatherServerExtensionsImpl.get_weather_batch(tonic::Request::new(WeatherBatchRequest {
+            queries: vec![query("OpenMeteo", None), query("Unknown", Some(location))],
+        })).await;
+        let items = reply.unwrap().into_inner().items;
+
+        assert_eq!(items.len(), 2);
+        for item in items {
+            match item.result {
+                Some(weather_batch_item::Result::Error(error)) => {
+                    assert_eq!(error.code, tonic::Code::InvalidArgument as i32);
+                    assert!(!error.details.is_empty());
+                },
+                other => panic!("Unexpected result {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_saved_locations_require_client_id() {
+        let reply = WeatherServerExtensionsImpl.list_saved_locations(tonic::Request::new(())).await;
+        assert_eq!(reply.err().unwrap().code(), tonic::Code::Unauthenticated);
+    }
+}
@@ -0,0 +1,237 @@
+use crate::defs;
+
+use lazy_static::lazy_static;
+use weather_service_rpc::{Location, WeatherForecast};
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Maximal number of locations whose forecasts are cached and whose requests are counted.
+const DEFAULT_CAPACITY: usize = 10000;
+
+lazy_static! {
+    static ref CACHE: Option<Arc<ForecastCache>> = {
+        let ttl = ttl();
+        if ttl.is_zero() { None } else { Some(Arc::new(ForecastCache::new(ttl))) }
+    };
+}
+
+/// Returns the time during which the fetched forecasts are served from the cache. Zero disables the cache.
+pub fn ttl() -> Duration {
+    let seconds = defs::CONFIG.general_section().get(defs::FORECAST_CACHE_TTL_KEY)
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(600);
+
+    Duration::from_secs(seconds)
+}
+
+/// Forecasts of the same provider for locations closer than 0.01 degree are shared.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Key {
+    provider: String,
+    lat: i32,
+    lon: i32,
+}
+
+impl Key {
+    fn new(provider: &str, location: &Location) -> Self {
+        Key {
+            provider: provider.to_string(),
+            lat: (location.lat * 100.0).round() as i32,
+            lon: (location.lon * 100.0).round() as i32,
+        }
+    }
+}
+
+struct Entry {
+    forecasts: Vec<WeatherForecast>,
+    fetched_at: Instant,
+}
+
+/// Location requested from a provider, along with the number of recent requests.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Request {
+    pub provider: String,
+    pub location: Location,
+    pub count: u32,
+}
+
+/// In-memory cache of the forecasts received from the providers.
+/// It also counts the requests per location, so the most requested ones can be refreshed ahead of expiry.
+/// Both the forecasts and the counters are kept for at most 'capacity' locations.
+pub struct ForecastCache {
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<HashMap<Key, Entry>>,
+    requests: Mutex<HashMap<Key, Request>>,
+}
+
+impl ForecastCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self::with_capacity(ttl, DEFAULT_CAPACITY)
+    }
+
+    pub fn with_capacity(ttl: Duration, capacity: usize) -> Self {
+        ForecastCache { ttl, capacity, entries: Mutex::new(HashMap::new()), requests: Mutex::new(HashMap::new()) }
+    }
+
+    /// Returns the forecasts if they were fetched less than TTL ago and counts the request.
+    /// When the counters are full, the location with the fewest requests stops being counted.
+    pub fn get(&self, provider: &str, location: &Location) -> Option<Vec<WeatherForecast>> {
+        let key = Key::new(provider, location);
+
+        let mut requests = self.requests.lock().unwrap();
+        if !requests.contains_key(&key) && requests.len() >= self.capacity {
+            let least_requested = requests.iter().min_by_key(|(_, request)| request.count).map(|(key, _)| key.clone());
+            if let Some(least_requested) = least_requested {
+                requests.remove(&least_requested);
+            }
+        }
+        requests.entry(key.clone())
+            .or_insert_with(|| Request { provider: provider.to_string(), location: location.clone(), count: 0 })
+            .count += 1;
+        drop(requests);
+
+        self.entries.lock().unwrap().get(&key)
+            .filter(|entry| entry.fetched_at.elapsed() < self.ttl)
+            .map(|entry| entry.forecasts.clone())
+    }
+
+    /// Caches the forecasts and evicts the expired ones. When the cache is still full,
+    /// the forecasts fetched first are evicted.
+    pub fn insert(&self, provider: &str, location: &Location, forecasts: Vec<WeatherForecast>) {
+        let key = Key::new(provider, location);
+        let mut entries = self.entries.lock().unwrap();
+
+        let ttl = self.ttl;
+        entries.retain(|_, entry| entry.fetched_at.elapsed() < ttl);
+        if !entries.contains_key(&key) && entries.len() >= self.capacity {
+            let oldest = entries.iter().min_by_key(|(_, entry)| entry.fetched_at).map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+
+        entries.insert(key, Entry { forecasts, fetched_at: Instant::now() });
+    }
+
+    /// Returns the time until the forecasts for the location expire. None if they are not cached or already expired.
+    pub fn expires_in(&self, provider: &str, location: &Location) -> Option<Duration> {
+        self.entries.lock().unwrap().get(&Key::new(provider, location))
+            .and_then(|entry| self.ttl.checked_sub(entry.fetched_at.elapsed()))
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Returns up to 'limit' locations with the most requests, the most requested first.
+    pub fn most_requested(&self, limit: usize) -> Vec<Request> {
+        let mut requests: Vec<Request> = self.requests.lock().unwrap().values().cloned().collect();
+        requests.sort_by_key(|request| std::cmp::Reverse(request.count));
+        requests.truncate(limit);
+
+        return requests;
+    }
+
+    /// Halves the request counters, so the old traffic weighs less than the recent one,
+    /// and removes the expired forecasts and the locations that are no longer requested.
+    pub fn decay(&self) {
+        self.requests.lock().unwrap().retain(|_, request| {
+            request.count /= 2;
+            request.count > 0
+        });
+
+        let ttl = self.ttl;
+        self.entries.lock().unwrap().retain(|_, entry| entry.fetched_at.elapsed() < ttl);
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+}
+
+/// Returns the cache configured by FORECAST_CACHE_TTL_SECS. None if the cache is disabled.
+pub fn global() -> Option<Arc<ForecastCache>> {
+    CACHE.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_location(lat: f32) -> Location {
+        Location {
+            name: "Name".to_string(),
+            state: "State".to_string(),
+            country: "Country".to_string(),
+            lon: 1.1,
+            lat,
+        }
+    }
+
+    fn get_forecast(avg_t: f32) -> WeatherForecast {
+        WeatherForecast { dt: 946684800, min_t: avg_t, max_t: avg_t, avg_t, condition: "Fine".to_string() }
+    }
+
+    #[test]
+    pub fn test_get_and_expire() {
+        let cache = ForecastCache::new(Duration::from_millis(50));
+        assert_eq!(cache.get("Stub", &get_location(2.2)), None);
+
+        cache.insert("Stub", &get_location(2.2), vec![get_forecast(20.0)]);
+        assert_eq!(cache.get("Stub", &get_location(2.201)), Some(vec![get_forecast(20.0)]));
+        assert_eq!(cache.get("Other", &get_location(2.2)), None);
+        assert!(cache.expires_in("Stub", &get_location(2.2)).is_some());
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(cache.get("Stub", &get_location(2.2)), None);
+        assert_eq!(cache.expires_in("Stub", &get_location(2.2)), None);
+    }
+
+    #[test]
+    pub fn test_most_requested() {
+        let cache = ForecastCache::new(Duration::from_secs(60));
+        for _ in 0..3 {
+            cache.get("Stub", &get_location(2.2));
+        }
+        cache.get("Stub", &get_location(3.3));
+        cache.get("Other", &get_location(2.2));
+        cache.get("Other", &get_location(2.2));
+
+        let requests = cache.most_requested(2);
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0], Request { provider: "Stub".to_string(), location: get_location(2.2), count: 3 });
+        assert_eq!(requests[1].provider, "Other");
+
+        cache.decay();
+        let requests = cache.most_requested(10);
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].count, 1);
+    }
+
+    #[test]
+    pub fn test_capacity() {
+        let cache = ForecastCache::with_capacity(Duration::from_millis(50), 2);
+        cache.get("Stub", &get_location(2.2));
+        cache.get("Stub", &get_location(2.2));
+        cache.get("Stub", &get_location(3.3));
+        cache.get("Stub", &get_location(4.4));
+
+        // The location with the fewest requests is replaced.
+        let requests = cache.most_requested(10);
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].location, get_location(2.2));
+        assert_eq!(requests[1].location, get_location(4.4));
+
+        cache.insert("Stub", &get_location(2.2), vec![get_forecast(20.0)]);
+        std::thread::sleep(Duration::from_millis(5));
+        cache.insert("Stub", &get_location(3.3), vec![get_forecast(21.0)]);
+        cache.insert("Stub", &get_location(4.4), vec![get_forecast(22.0)]);
+        assert_eq!(cache.entries.lock().unwrap().len(), 2);
+        assert!(cache.expires_in("Stub", &get_location(2.2)).is_none());
+
+        // The expired forecasts are evicted by the next insert.
+        std::thread::sleep(Duration::from_millis(60));
+        cache.insert("Stub", &get_location(5.5), vec![get_forecast(23.0)]);
+        assert_eq!(cache.entries.lock().unwrap().len(), 1);
+    }
+}
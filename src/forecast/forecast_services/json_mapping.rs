@@ -0,0 +1,310 @@
+use crate::defs;
+use crate::forecast;
+use crate::forecast::registry::{Capability, ProviderMetadata};
+
+use ini::Properties;
+use serde_json::Value;
+use weather_service_rpc::{Location, WeatherForecast};
+
+use std::sync::Arc;
+
+/// Prefix of the configuration sections that define JSON-mapping providers, e.g. [provider:MyWeather].
+pub static SECTION_PREFIX: &str = "provider:";
+
+/// Description of a provider whose forecasts are extracted from the JSON reply by field paths.
+/// A path is a sequence of object keys and array indices separated by dots, e.g. 'forecast.days' or 'weather.0.main'.
+#[derive(Debug)]
+pub struct Mapping {
+    /// Url with {lat}, {lon} and {key} placeholders.
+    pub url_template: String,
+    /// Path to the array of daily forecasts.
+    pub daily_path: String,
+    /// Paths to the fields of an item of the daily array.
+    pub timestamp_field: String,
+    pub min_field: String,
+    pub max_field: String,
+    pub avg_field: Option<String>,
+    pub condition_field: Option<String>,
+    pub max_forecast_days: u32,
+    pub units: String,
+}
+
+impl Mapping {
+    /// Reads the mapping from the configuration section.
+    pub fn from_section(section: &Properties) -> Result<Self, String> {
+        let required = |field: &str| {
+            section.get(field).map(str::to_string).ok_or(format!("'{}' is not specified", field))
+        };
+
+        let max_forecast_days = match section.get("MAX_FORECAST_DAYS") {
+            Some(value) => value.parse::<u32>().or(Err(format!("'MAX_FORECAST_DAYS' is invalid: {}", value)))?,
+            None => 7,
+        };
+
+        Ok(Self {
+            url_template: required("URL_TEMPLATE")?,
+            daily_path: required("DAILY_PATH")?,
+            timestamp_field: required("TIMESTAMP_FIELD")?,
+            min_field: required("MIN_FIELD")?,
+            max_field: required("MAX_FIELD")?,
+            avg_field: section.get("AVG_FIELD").map(str::to_string),
+            condition_field: section.get("CONDITION_FIELD").map(str::to_string),
+            max_forecast_days,
+            units: section.get("UNITS").unwrap_or("metric").to_string(),
+        })
+    }
+
+    pub fn requires_key(&self) -> bool {
+        self.url_template.contains("{key}")
+    }
+}
+
+/// Returns the general configuration field that holds the API key of the provider, e.g. MYWEATHER_AUTHORIZATION.
+pub fn authorization_key(name: &str) -> String {
+    format!("{}_AUTHORIZATION", name.to_uppercase())
+}
+
+pub fn metadata(name: &str, mapping: &Mapping) -> ProviderMetadata {
+    ProviderMetadata {
+        capabilities: vec![Capability::Daily],
+        max_forecast_days: mapping.max_forecast_days,
+        units: vec![mapping.units.clone()],
+        languages: vec!["en".to_string()],
+        authorization_key: if mapping.requires_key() { Some(authorization_key(name)) } else { None },
+    }
+}
+
+/// Returns the value at the path. An empty path refers to the value itself.
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').filter(|key| !key.is_empty()).try_fold(value, |value, key| {
+        match value {
+            Value::Object(object) => object.get(key),
+            Value::Array(array) => array.get(key.parse::<usize>().ok()?),
+            _ => None,
+        }
+    })
+}
+
+fn to_f32(value: &Value) -> Option<f32> {
+    match value {
+        Value::Number(number) => number.as_f64().map(|number| number as f32),
+        Value::String(string) => string.parse::<f32>().ok(),
+        _ => None,
+    }
+}
+
+/// Accepts unix time in seconds, RFC 3339 date-time or yyyy-mm-dd date.
+fn to_timestamp(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(number) => number.as_i64(),
+        Value::String(string) => {
+            if let Ok(date_time) = chrono::DateTime::parse_from_rfc3339(string) {
+                return Some(date_time.timestamp());
+            }
+            chrono::NaiveDate::parse_from_str(string, "%Y-%m-%d").ok().map(|date| date.and_hms(0, 0, 0).timestamp())
+        },
+        _ => None,
+    }
+}
+
+// An implementation of ForecastEndService for the providers defined in the configuration file.
+pub struct Integration {
+    name: String,
+    mapping: Arc<Mapping>,
+}
+
+impl Integration {
+    pub fn new(name: &str, mapping: Arc<Mapping>) -> Self {
+        Self { name: name.to_string(), mapping }
+    }
+
+    fn make_error(&self, description: String) -> forecast::Error {
+        forecast::Error::Internal { provider: Some(self.name.clone()), description }
+    }
+
+    fn to_forecast(&self, item: &Value) -> Result<WeatherForecast, forecast::Error> {
+        let field = |path: &str| {
+            lookup(item, path).ok_or_else(|| self.make_error(format!("Field '{}' is missing in the daily forecast", path)))
+        };
+        let temperature = |path: &str| {
+            to_f32(field(path)?).ok_or_else(|| self.make_error(format!("Field '{}' is not a temperature", path)))
+        };
+
+        let dt = to_timestamp(field(&self.mapping.timestamp_field)?).ok_or_else(
+            || self.make_error(format!("Field '{}' is not a timestamp", self.mapping.timestamp_field)))?;
+        let min_t = temperature(&self.mapping.min_field)?;
+        let max_t = temperature(&self.mapping.max_field)?;
+
+        let avg_t = match &self.mapping.avg_field {
+            Some(path) => temperature(path)?,
+            None => (min_t + max_t) / 2.0,
+        };
+
+        let condition = self.mapping.condition_field.as_ref()
+            .and_then(|path| lookup(item, path))
+            .map_or(String::new(), |value| match value {
+                Value::String(string) => string.clone(),
+                other => other.to_string(),
+            });
+
+        Ok(WeatherForecast { dt, min_t, max_t, avg_t, condition })
+    }
+}
+
+/// Substitutes the placeholders of the template. The key is URL-encoded, since it comes from the configuration
+/// and may contain characters that would change the meaning of the URL, e.g. '&' or '#'.
+fn expand_url_template(template: &str, loc: &Location, key: &str) -> String {
+    template
+        .replace("{lat}", &loc.lat.to_string())
+        .replace("{lon}", &loc.lon.to_string())
+        .replace("{key}", &url::form_urlencoded::byte_serialize(key.as_bytes()).collect::<String>())
+}
+
+#[tonic::async_trait]
+impl forecast::ForecastEndService for Integration {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn get_url(&self, loc: Location) -> String {
+        let key = defs::CONFIG.general_section().get(authorization_key(&self.name)).unwrap_or_default();
+
+        expand_url_template(&self.mapping.url_template, &loc, key)
+    }
+
+    async fn handle_response(&self, response: reqwest::Response) -> Result<Vec<WeatherForecast>, forecast::Error> {
+        let reply = response.json::<Value>().await.or_else(
+            |err| Err(self.make_error(format!("Unable to process the response from {}. {}", self.name, err))))?;
+
+        let daily = lookup(&reply, &self.mapping.daily_path).and_then(Value::as_array).ok_or_else(
+            || self.make_error(format!("There is no daily forecasts array at '{}'", self.mapping.daily_path)))?;
+
+        daily.iter().map(|item| self.to_forecast(item)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::forecast::{Location, ForecastEndService};
+    use crate::forecast::response_with_body;
+
+    fn get_mapping() -> Mapping {
+        let config = ini::Ini::load_from_str(r#"
+            [provider:Test]
+            URL_TEMPLATE = http://example.com/forecast?lat={lat}&lon={lon}
+            DAILY_PATH = data.days
+            TIMESTAMP_FIELD = date
+            MIN_FIELD = temperature.min
+            MAX_FIELD = temperature.max
+            CONDITION_FIELD = conditions.0.text
+            MAX_FORECAST_DAYS = 5
+        "#).unwrap();
+
+        Mapping::from_section(config.section(Some("provider:Test")).unwrap()).unwrap()
+    }
+
+    #[test]
+    pub fn test_mapping_from_section() {
+        let mapping = get_mapping();
+
+        assert_eq!(mapping.daily_path, "data.days");
+        assert_eq!(mapping.avg_field, None);
+        assert_eq!(mapping.max_forecast_days, 5);
+        assert_eq!(mapping.units, "metric");
+        assert!(!mapping.requires_key());
+
+        let config = ini::Ini::load_from_str("[provider:Test]\nURL_TEMPLATE = http://example.com/").unwrap();
+        let err = Mapping::from_section(config.section(Some("provider:Test")).unwrap()).unwrap_err();
+        assert_eq!(err, "'DAILY_PATH' is not specified");
+    }
+
+    #[test]
+    pub fn test_lookup() {
+        let value = serde_json::json!({"a": {"b": [1, {"c": "d"}]}});
+
+        assert_eq!(lookup(&value, ""), Some(&value));
+        assert_eq!(lookup(&value, "a.b.0"), Some(&serde_json::json!(1)));
+        assert_eq!(lookup(&value, "a.b.1.c"), Some(&serde_json::json!("d")));
+        assert_eq!(lookup(&value, "a.b.2"), None);
+        assert_eq!(lookup(&value, "a.x"), None);
+    }
+
+    #[test]
+    pub fn test_url_is_valid() {
+        let loc = Location {
+            name: "Name".to_string(),
+            state: "State".to_string(),
+            country: "Country".to_string(),
+            lon: 1.1,
+            lat: 2.2,
+        };
+
+        let url = Integration::new("Test", Arc::new(get_mapping())).get_url(loc);
+
+        assert_eq!(url, "http://example.com/forecast?lat=2.2&lon=1.1")
+    }
+
+    #[test]
+    pub fn test_key_is_encoded() {
+        let loc = Location {
+            name: "Name".to_string(),
+            state: "State".to_string(),
+            country: "Country".to_string(),
+            lon: 1.1,
+            lat: 2.2,
+        };
+
+        let url = expand_url_template("http://example.com/forecast?lat={lat}&lon={lon}&key={key}", &loc, "a b&c=d#e");
+
+        assert_eq!(url, "http://example.com/forecast?lat=2.2&lon=1.1&key=a+b%26c%3Dd%23e")
+    }
+
+    #[tokio::test]
+    pub async fn parse_response_test() {
+        let data = r#"
+        {
+            "data": {
+                "days": [
+                    {
+                        "date": "2000-01-01",
+                        "temperature": {"min": 19.5, "max": 20.5},
+                        "conditions": [{"text": "It's warm and good!"}]
+                    },
+                    {
+                        "date": 946771200,
+                        "temperature": {"min": "22.5", "max": "23.5"},
+                        "conditions": []
+                    }
+                ]
+            }
+        }"#;
+
+        let integration = Integration::new("Test", Arc::new(get_mapping()));
+        let forecasts = integration.handle_response(response_with_body(data).await).await.unwrap();
+
+        assert_eq!(forecasts.len(), 2);
+
+        assert_eq!(forecasts[0].dt, 946684800);
+        assert_eq!(forecasts[0].max_t, 20.5);
+        assert_eq!(forecasts[0].min_t, 19.5);
+        assert_eq!(forecasts[0].avg_t, 20.0);
+        assert_eq!(forecasts[0].condition, "It's warm and good!");
+
+        assert_eq!(forecasts[1].dt, 946771200);
+        assert_eq!(forecasts[1].max_t, 23.5);
+        assert_eq!(forecasts[1].min_t, 22.5);
+        assert_eq!(forecasts[1].avg_t, 23.0);
+        assert_eq!(forecasts[1].condition, "");
+    }
+
+    #[tokio::test]
+    pub async fn malformed_response_test() {
+        let integration = Integration::new("Test", Arc::new(get_mapping()));
+
+        for body in ["not a json", r#"{"data": {"days": {}}}"#, r#"{"data": {"days": [{"date": "today"}]}}"#] {
+            let result = integration.handle_response(response_with_body(body).await).await;
+            assert_eq!(result.err().unwrap().code(), tonic::Code::Internal);
+        }
+    }
+}
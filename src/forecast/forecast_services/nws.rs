@@ -0,0 +1,366 @@
+use crate::forecast;
+use crate::forecast::registry::{Capability, ProviderMetadata};
+use crate::metrics;
+
+use lazy_static::lazy_static;
+use serde::{Serialize, Deserialize};
+use weather_service_rpc::{Location, WeatherForecast};
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+#[derive(Serialize, Deserialize)]
+struct JSONPointsReply {
+    properties: PointProperties,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PointProperties {
+    forecast: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JSONForecastReply {
+    properties: ForecastProperties,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ForecastProperties {
+    periods: Vec<Period>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Period {
+    start_time: String,
+    is_daytime: bool,
+    temperature: f32,
+    short_forecast: String,
+}
+
+/// Number of locations whose forecast url is kept by the grid cache.
+const GRID_CACHE_CAPACITY: usize = 10000;
+
+lazy_static! {
+    static ref GRID_CACHE: Arc<GridCache> = Arc::new(GridCache::new(GRID_CACHE_CAPACITY));
+}
+
+/// The grid of a location never changes, so the forecast url is requested once per location.
+/// When the cache is full, the location that was added first is evicted.
+struct GridCache {
+    capacity: usize,
+    entries: Mutex<(HashMap<String, String>, VecDeque<String>)>,
+}
+
+impl GridCache {
+    fn new(capacity: usize) -> Self {
+        Self { capacity, entries: Mutex::new((HashMap::new(), VecDeque::new())) }
+    }
+
+    fn get(&self, points_url: &str) -> Option<String> {
+        self.entries.lock().unwrap().0.get(points_url).cloned()
+    }
+
+    fn insert(&self, points_url: String, forecast_url: String) {
+        let mut entries = self.entries.lock().unwrap();
+        let (urls, order) = &mut *entries;
+
+        if urls.insert(points_url.clone(), forecast_url).is_none() {
+            order.push_back(points_url);
+        }
+        while urls.len() > self.capacity {
+            match order.pop_front() {
+                Some(oldest) => { urls.remove(&oldest); },
+                None => break,
+            }
+        }
+    }
+}
+
+/// Daytime and overnight periods that start on the same local date.
+#[derive(Default)]
+struct DayPeriods {
+    day: Option<Period>,
+    night: Option<Period>,
+}
+
+fn to_daily_forecast(date: chrono::NaiveDate, periods: DayPeriods) -> Option<WeatherForecast> {
+    let day_t = periods.day.as_ref().map(|period| period.temperature);
+    let night_t = periods.night.as_ref().map(|period| period.temperature);
+    let condition = periods.day.or(periods.night)?.short_forecast;
+
+    let max_t = day_t.or(night_t)?;
+    let min_t = night_t.or(day_t)?;
+
+    Some(WeatherForecast {
+        dt: date.and_hms(0, 0, 0).timestamp(),
+        min_t: min_t.min(max_t),
+        max_t: max_t.max(min_t),
+        avg_t: (min_t + max_t) / 2.0,
+        condition
+    })
+}
+
+pub const NAME: &str = "NationalWeatherService";
+
+pub fn metadata() -> ProviderMetadata {
+    ProviderMetadata {
+        capabilities: vec![Capability::Daily],
+        max_forecast_days: 7,
+        units: vec!["metric".to_string()],
+        languages: vec!["en".to_string()],
+        authorization_key: None,
+    }
+}
+
+// An implementation of ForecastEndService to provide nessesary data and hanle reply from US National Weather Service.
+// The forecast is requested in two steps: the location is resolved to the grid of the forecast office
+// and then the forecast for the grid is requested. api.weather.gov doesn't require an API key.
+pub struct Integration {
+    base_url: String,
+    grid_cache: Arc<GridCache>,
+}
+
+impl Default for Integration {
+    fn default() -> Self {
+        Self { base_url: "https://api.weather.gov".to_string(), grid_cache: GRID_CACHE.clone() }
+    }
+}
+
+impl Integration {
+    /// Returns the url of the forecast for the grid that contains the location.
+    async fn get_forecast_url(&self, loc: Location) -> Result<String, forecast::Error> {
+        let points_url = forecast::ForecastEndService::get_url(self, loc);
+
+        let cached = self.grid_cache.get(&points_url);
+        metrics::record_cache_lookup("nws_grid", cached.is_some());
+        if let Some(forecast_url) = cached {
+            return Ok(forecast_url);
+        }
+
+        let response = forecast::request(self, &points_url).await?;
+        let points_reply = response.json::<JSONPointsReply>().await.or_else(|err| {
+            metrics::record_upstream_error(&forecast::ForecastEndService::name(self), "parse");
+            return Err(forecast::Error::Internal {
+                provider: Some(forecast::ForecastEndService::name(self)),
+                description: format!("Unable to process the grid of the location from National Weather Service. {}", err)
+            })
+        })?;
+
+        let forecast_url = format!("{}?units=si", points_reply.properties.forecast);
+        self.grid_cache.insert(points_url, forecast_url.clone());
+
+        Ok(forecast_url)
+    }
+}
+
+#[tonic::async_trait]
+impl forecast::ForecastEndService for Integration {
+    fn name(&self) -> String {
+        NAME.to_string()
+    }
+
+    // NWS redirects requests with more than 4 decimal places in the coordinates.
+    fn get_url(&self, loc: Location) -> String {
+        return format!("{}/points/{:.4},{:.4}", self.base_url, loc.lat, loc.lon);
+    }
+
+    async fn handle_response(&self, response: reqwest::Response) -> Result<Vec<WeatherForecast>, forecast::Error> {
+        let nws_reply = response.json::<JSONForecastReply>().await.or_else(
+            |err| return Err(forecast::Error::Internal {
+                provider: Some(self.name()),
+                description: format!("Unable to process the response from National Weather Service. {}", err)
+            }))?;
+
+        let mut days: BTreeMap<chrono::NaiveDate, DayPeriods> = BTreeMap::new();
+        for period in nws_reply.properties.periods {
+            let date = match chrono::DateTime::parse_from_rfc3339(&period.start_time) {
+                Ok(start_time) => start_time.naive_local().date(),
+                Err(_) => continue,
+            };
+
+            let day = days.entry(date).or_default();
+            if period.is_daytime {
+                day.day.get_or_insert(period);
+            }
+            else {
+                day.night.get_or_insert(period);
+            }
+        }
+
+        let received_locations: Vec<WeatherForecast> = days.into_iter().filter_map(
+            |(date, periods)| to_daily_forecast(date, periods)).collect();
+
+        return Ok(received_locations);
+    }
+
+    async fn get_forecasts(&self, loc: Location) -> Result<Vec<WeatherForecast>, forecast::Error> {
+        let forecast_url = self.get_forecast_url(loc).await?;
+        let response = forecast::request(self, &forecast_url).await?;
+
+        self.handle_response(response).await.map_err(|err| {
+            metrics::record_upstream_error(&self.name(), "parse");
+            err
+        })
+    }
+
+    // NWS covers only the US territory.
+    fn probe_location(&self) -> Location {
+        Location {
+            name: "Washington".to_string(),
+            state: "DC".to_string(),
+            country: "US".to_string(),
+            lon: -77.0365,
+            lat: 38.8977,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::forecast::{Location, ForecastEndService};
+    use httpmock::prelude::*;
+
+    fn integration(base_url: String) -> Integration {
+        Integration { base_url, grid_cache: Arc::new(GridCache::new(GRID_CACHE_CAPACITY)) }
+    }
+
+    fn get_location() -> Location {
+        Location {
+            name: "Name".to_string(),
+            state: "State".to_string(),
+            country: "Country".to_string(),
+            lon: -77.03651,
+            lat: 38.8977,
+        }
+    }
+
+    #[test]
+    pub fn test_url_is_valid() {
+        let url = Integration::default().get_url(get_location());
+
+        assert_eq!(url, "https://api.weather.gov/points/38.8977,-77.0365")
+    }
+
+    #[tokio::test]
+    pub async fn parse_response_test() {
+        let data = r#"
+        {
+            "properties": {
+                "periods": [
+                    {
+                        "number": 1,
+                        "name": "Tonight",
+                        "startTime": "1999-12-31T18:00:00-05:00",
+                        "isDaytime": false,
+                        "temperature": 12,
+                        "shortForecast": "Mostly Clear"
+                    },
+                    {
+                        "number": 2,
+                        "name": "Saturday",
+                        "startTime": "2000-01-01T06:00:00-05:00",
+                        "isDaytime": true,
+                        "temperature": 20.5,
+                        "shortForecast": "Sunny"
+                    },
+                    {
+                        "number": 3,
+                        "name": "Saturday Night",
+                        "startTime": "2000-01-01T18:00:00-05:00",
+                        "isDaytime": false,
+                        "temperature": 19.5,
+                        "shortForecast": "Partly Cloudy"
+                    }
+                ]
+            }
+        }"#;
+
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/forecast");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(data);
+        });
+
+        let url = reqwest::Url::parse(&format!("{}/forecast", server.base_url())).unwrap();
+        let response = reqwest::Client::new().get(url).send().await.unwrap();
+
+        let forecasts = Integration::default().handle_response(response).await.unwrap();
+
+        assert_eq!(forecasts.len(), 2);
+
+        // Only the night is left from the first day.
+        assert_eq!(forecasts[0].dt, 946598400);
+        assert_eq!(forecasts[0].max_t, 12.0);
+        assert_eq!(forecasts[0].min_t, 12.0);
+        assert_eq!(forecasts[0].condition, "Mostly Clear");
+
+        assert_eq!(forecasts[1].dt, 946684800);
+        assert_eq!(forecasts[1].max_t, 20.5);
+        assert_eq!(forecasts[1].min_t, 19.5);
+        assert_eq!(forecasts[1].avg_t, 20.0);
+        assert_eq!(forecasts[1].condition, "Sunny");
+    }
+
+    #[tokio::test]
+    pub async fn test_grid_is_cached() {
+        let server = MockServer::start();
+        let points_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/points/38.8977,-77.0365");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(format!(r#"{{"properties": {{"forecast": "{}/gridpoints/LWX/97,71/forecast"}}}}"#,
+                              server.base_url()));
+        });
+        let forecast_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/gridpoints/LWX/97,71/forecast")
+                .query_param("units", "si");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(r#"{"properties": {"periods": []}}"#);
+        });
+
+        let integration = integration(server.base_url());
+        assert!(integration.get_forecasts(get_location()).await.is_ok());
+        assert!(integration.get_forecasts(get_location()).await.is_ok());
+
+        assert_eq!(points_mock.hits(), 1);
+        assert_eq!(forecast_mock.hits(), 2);
+    }
+
+    #[tokio::test]
+    pub async fn test_location_outside_of_us() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/points/38.8977,-77.0365");
+            then.status(404)
+                .header("content-type", "application/problem+json")
+                .body(r#"{"title": "Data Unavailable For Requested Point", "status": 404}"#);
+        });
+
+        let integration = integration(server.base_url());
+        let result = integration.get_forecasts(get_location()).await;
+
+        assert_eq!(result.err().unwrap().code(), tonic::Code::NotFound);
+    }
+
+    #[test]
+    pub fn test_grid_cache_is_bounded() {
+        let cache = GridCache::new(2);
+        cache.insert("a".to_string(), "forecast a".to_string());
+        cache.insert("b".to_string(), "forecast b".to_string());
+        cache.insert("a".to_string(), "forecast a".to_string());
+        cache.insert("c".to_string(), "forecast c".to_string());
+
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.get("b"), Some("forecast b".to_string()));
+        assert_eq!(cache.get("c"), Some("forecast c".to_string()));
+    }
+}
@@ -0,0 +1,199 @@
+use crate::forecast;
+use crate::forecast::registry::{Capability, ProviderMetadata};
+
+use serde::{Serialize, Deserialize};
+use weather_service_rpc::{Location, WeatherForecast};
+
+const SECONDS_IN_DAY: i64 = 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize)]
+struct JSONReply {
+    daily: DailyData,
+    hourly: HourlyData,
+}
+
+// Open-Meteo returns a column per requested variable. A value is null when the model has no data for it.
+#[derive(Serialize, Deserialize)]
+struct DailyData {
+    time: Vec<i64>,
+    temperature_2m_max: Vec<Option<f32>>,
+    temperature_2m_min: Vec<Option<f32>>,
+    weathercode: Vec<Option<u8>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct HourlyData {
+    time: Vec<i64>,
+    temperature_2m: Vec<Option<f32>>,
+}
+
+/// Returns the description of the WMO weather interpretation code.
+/// See https://open-meteo.com/en/docs (WMO Weather interpretation codes).
+fn describe_weather_code(code: u8) -> &'static str {
+    match code {
+        0 => "Clear sky",
+        1 => "Mainly clear",
+        2 => "Partly cloudy",
+        3 => "Overcast",
+        45 | 48 => "Fog",
+        51 | 53 | 55 => "Drizzle",
+        56 | 57 => "Freezing drizzle",
+        61 | 63 | 65 => "Rain",
+        66 | 67 => "Freezing rain",
+        71 | 73 | 75 => "Snow fall",
+        77 => "Snow grains",
+        80 | 81 | 82 => "Rain showers",
+        85 | 86 => "Snow showers",
+        95 => "Thunderstorm",
+        96 | 99 => "Thunderstorm with hail",
+        _ => "Unknown",
+    }
+}
+
+/// Averages the hourly temperatures that fall into the day starting at 'day_start'.
+fn average_temperature(hourly: &HourlyData, day_start: i64) -> Option<f32> {
+    let temperatures: Vec<f32> = hourly.time.iter().zip(hourly.temperature_2m.iter())
+        .filter(|(time, _)| **time >= day_start && **time < day_start + SECONDS_IN_DAY)
+        .filter_map(|(_, temperature)| *temperature)
+        .collect();
+
+    if temperatures.is_empty() {
+        return None;
+    }
+
+    Some(temperatures.iter().sum::<f32>() / temperatures.len() as f32)
+}
+
+pub const NAME: &str = "OpenMeteo";
+
+pub fn metadata() -> ProviderMetadata {
+    ProviderMetadata {
+        capabilities: vec![Capability::Daily],
+        max_forecast_days: 7,
+        units: vec!["metric".to_string()],
+        languages: vec!["en".to_string()],
+        authorization_key: None,
+    }
+}
+
+// An implementation of ForecastEndService to provide nessesary data and hanle reply from Open-Meteo service.
+// Open-Meteo is free for non-commercial use and doesn't require an API key.
+pub struct Integration;
+#[tonic::async_trait]
+impl forecast::ForecastEndService for Integration {
+    fn name(&self) -> String {
+        NAME.to_string()
+    }
+
+    fn get_url(&self, loc: Location) -> String {
+        let daily = "temperature_2m_max,temperature_2m_min,weathercode";
+        // Open-Meteo has no daily mean temperature, so the hourly ones are requested only to compute 'avg_t'.
+        // They are not exposed, hence the provider declares only the 'Daily' capability.
+        let hourly = "temperature_2m";
+
+        return format!(
+            "https://api.open-meteo.com/v1/forecast?latitude={}&longitude={}&daily={}&hourly={}&timeformat=unixtime&timezone=UTC",
+            loc.lat, loc.lon, daily, hourly);
+    }
+
+    async fn handle_response(&self, response: reqwest::Response) -> Result<Vec<WeatherForecast>, forecast::Error> {
+        let openmeteo_reply = response.json::<JSONReply>().await.or_else(
+            |err| return Err(forecast::Error::Internal {
+                provider: Some(self.name()),
+                description: format!("Unable to process the response from Open-Meteo. {}", err)
+            }))?;
+
+        let daily = &openmeteo_reply.daily;
+        let received_locations: Vec<WeatherForecast> = daily.time.iter()
+            .zip(daily.temperature_2m_min.iter().zip(daily.temperature_2m_max.iter()))
+            .zip(daily.weathercode.iter())
+            .filter_map(|((dt, (min_t, max_t)), code)| {
+                // Days at the end of the forecast range may have no data yet.
+                let (min_t, max_t) = ((*min_t)?, (*max_t)?);
+
+                return Some(WeatherForecast {
+                    dt: *dt,
+                    min_t,
+                    max_t,
+                    avg_t: average_temperature(&openmeteo_reply.hourly, *dt).unwrap_or((min_t + max_t) / 2.0),
+                    condition: code.map_or(String::new(), |code| describe_weather_code(code).to_string())
+                });
+            }).collect();
+
+        return Ok(received_locations);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::forecast::{Location, ForecastEndService};
+    use crate::forecast::response_with_body;
+
+    #[test]
+    pub fn test_url_is_valid() {
+        let loc = Location {
+            name: "Name".to_string(),
+            state: "State".to_string(),
+            country: "Country".to_string(),
+            lon: 1.1,
+            lat: 2.2,
+        };
+
+        let url = Integration.get_url(loc);
+
+        assert_eq!(url,
+            concat!("https://api.open-meteo.com/v1/forecast?latitude=2.2&longitude=1.1",
+                    "&daily=temperature_2m_max,temperature_2m_min,weathercode&hourly=temperature_2m",
+                    "&timeformat=unixtime&timezone=UTC"))
+    }
+
+    #[tokio::test]
+    pub async fn malformed_response_test() {
+        for body in ["", "not a json", r#"{"daily": [], "hourly": []}"#, r#"{"daily": {"time": [1]}}"#] {
+            let result = Integration.handle_response(response_with_body(body).await).await;
+            assert_eq!(result.err().unwrap().code(), tonic::Code::Internal);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn parse_response_test() {
+        let data = r#"
+        {
+            "daily": {
+                "time": [946684800, 946771200, 946857600],
+                "temperature_2m_max": [20.5, 23.5, null],
+                "temperature_2m_min": [19.5, 22.5, null],
+                "weathercode": [0, null, null]
+            },
+            "hourly": {
+                "time": [946684800, 946728000, 946771200],
+                "temperature_2m": [19.0, 21.0, null]
+            }
+        }"#;
+
+        let forecasts = Integration.handle_response(response_with_body(data).await).await.unwrap();
+
+        assert_eq!(forecasts.len(), 2);
+
+        assert_eq!(forecasts[0].dt, 946684800);
+        assert_eq!(forecasts[0].max_t, 20.5);
+        assert_eq!(forecasts[0].min_t, 19.5);
+        assert_eq!(forecasts[0].avg_t, 20.0);
+        assert_eq!(forecasts[0].condition, "Clear sky");
+
+        // There are no hourly temperatures for the second day, so the average is taken from the daily ones.
+        assert_eq!(forecasts[1].dt, 946771200);
+        assert_eq!(forecasts[1].max_t, 23.5);
+        assert_eq!(forecasts[1].min_t, 22.5);
+        assert_eq!(forecasts[1].avg_t, 23.0);
+        assert_eq!(forecasts[1].condition, "");
+    }
+
+    #[test]
+    pub fn test_describe_weather_code() {
+        assert_eq!(describe_weather_code(3), "Overcast");
+        assert_eq!(describe_weather_code(95), "Thunderstorm");
+        assert_eq!(describe_weather_code(200), "Unknown");
+    }
+}
@@ -1,5 +1,6 @@
 use crate::defs;
 use crate::forecast;
+use crate::forecast::registry::{Capability, ProviderMetadata};
 
 use lazy_static::lazy_static;
 use serde::{Serialize, Deserialize};
@@ -30,16 +31,85 @@ main: String,
     description: String
 }
 
+#[derive(Serialize, Deserialize)]
+struct JSONHistoryReply {
+    current: Observation,
+    hourly: Vec<Observation>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Observation {
+    dt: i64,
+    temp: f32,
+    weather: Vec<Condition>,
+}
+
+fn describe(weather: &[Condition]) -> String {
+    weather.first().map_or(String::new(), |condition| format!("{}, {}", condition.main, condition.description))
+}
+
 lazy_static! {
     static ref OPENWEATHERMAP_AUTHORIZATION: String = {
-        return defs::CONFIG.general_section().get("OPENWEATHERMAP_AUTHORIZATION").unwrap().to_string();
+        return defs::CONFIG.general_section().get("OPENWEATHERMAP_AUTHORIZATION").unwrap_or_default().to_string();
     };
 }
 
+pub const NAME: &str = "OpenWeatherMap";
+
+pub fn metadata() -> ProviderMetadata {
+    ProviderMetadata {
+        capabilities: vec![Capability::Daily, Capability::History],
+        max_forecast_days: 8,
+        units: vec!["metric".to_string()],
+        languages: vec!["en".to_string()],
+        authorization_key: Some("OPENWEATHERMAP_AUTHORIZATION".to_string()),
+    }
+}
+
+/// Number of past days for which the history is available.
+const HISTORY_DAYS: i64 = 5;
+
+// The history is available for the last HISTORY_DAYS days. The reply contains the hourly observations for the whole day
+// in UTC and the 'current' observation at the requested time, which is used for the condition.
+fn get_history_url(loc: &Location, date: chrono::NaiveDate) -> String {
+    return format!(
+        "https://api.openweathermap.org/data/2.5/onecall/timemachine?lat={}&lon={}&dt={}&units=metric&appid={}",
+        loc.lat, loc.lon, date.and_hms(12, 0, 0).timestamp(), *OPENWEATHERMAP_AUTHORIZATION);
+}
+
+async fn parse_history(response: reqwest::Response, date: chrono::NaiveDate) -> Result<WeatherForecast, forecast::Error> {
+    let history_reply = response.json::<JSONHistoryReply>().await.or_else(
+        |err| return Err(forecast::Error::Internal {
+            provider: Some(NAME.to_string()),
+            description: format!("Unable to process the history from OpenWeatherMap. {}", err)
+        }))?;
+
+    let temperatures: Vec<f32> = history_reply.hourly.iter().filter(|observation| {
+        return chrono::NaiveDateTime::from_timestamp_opt(observation.dt, 0)
+            .map_or(false, |date_time| date_time.date() == date);
+    }).map(|observation| observation.temp).collect();
+
+    if temperatures.is_empty() {
+        return Err(forecast::Error::DateOutOfRange { provider: NAME.to_string(), date: date.format("%m.%d.%Y").to_string() });
+    }
+
+    Ok(WeatherForecast {
+        dt: date.and_hms(0, 0, 0).timestamp(),
+        min_t: temperatures.iter().cloned().fold(f32::INFINITY, f32::min),
+        max_t: temperatures.iter().cloned().fold(f32::NEG_INFINITY, f32::max),
+        avg_t: temperatures.iter().sum::<f32>() / temperatures.len() as f32,
+        condition: describe(&history_reply.current.weather),
+    })
+}
+
 // An implementation of ForecastEndService to provide nessesary data and hanle reply from WeatherAPI service.
 pub struct Integration;
 #[tonic::async_trait]
 impl forecast::ForecastEndService for Integration {
+    fn name(&self) -> String {
+        NAME.to_string()
+    }
+
     fn get_url(&self, loc: Location) -> String {
         let forecast_excludes = "current,minutely,hourly";
         let units = "metric";
@@ -51,9 +121,9 @@ impl forecast::ForecastEndService for Integration {
 
     async fn handle_response(&self, response: reqwest::Response) -> Result<Vec<WeatherForecast>, forecast::Error> {
         let openweather_reply = response.json::<JSONReply>().await.or_else(
-            |err| return Err(forecast::Error { 
-                code: forecast::ErrorCode::Internal,
-                description: format!("Unable to process the response from OpenWeatherMap. {}", err) 
+            |err| return Err(forecast::Error::Internal {
+                provider: Some(self.name()),
+                description: format!("Unable to process the response from OpenWeatherMap. {}", err)
             }))?;
 
         let received_locations: Vec<WeatherForecast> = openweather_reply.daily.into_iter().map(|item| {
@@ -62,17 +132,29 @@ impl forecast::ForecastEndService for Integration {
                 min_t: item.temp.min,
                 max_t: item.temp.max,
                 avg_t: item.temp.day,
-                condition: format!("{}, {}", item.weather[0].main, item.weather[0].description)
+                condition: describe(&item.weather)
             }
         }).collect();
         return Ok(received_locations);
     }
+
+    async fn get_history(&self, loc: Location, date: chrono::NaiveDate) -> Result<WeatherForecast, forecast::Error> {
+        // OpenWeatherMap rejects older dates with 400, which would be reported as an invalid request.
+        if date < chrono::Utc::today().naive_utc() - chrono::Duration::days(HISTORY_DAYS) {
+            return Err(forecast::Error::DateOutOfRange { provider: NAME.to_string(), date: date.format("%m.%d.%Y").to_string() });
+        }
+
+        let response = forecast::request(self, &get_history_url(&loc, date)).await?;
+
+        parse_history(response, date).await
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::forecast::{Location, ForecastEndService};
+    use crate::forecast::response_with_body;
     use httpmock::prelude::*;
 
     #[test]
@@ -93,6 +175,84 @@ mod tests {
                     *OPENWEATHERMAP_AUTHORIZATION))
     }
 
+    #[test]
+    pub fn test_history_url_is_valid() {
+        let loc = Location {
+            name: "Name".to_string(),
+            state: "State".to_string(),
+            country: "Country".to_string(),
+            lon: 1.1,
+            lat: 2.2,
+        };
+
+        let url = get_history_url(&loc, chrono::NaiveDate::from_ymd(2000, 1, 1));
+
+        assert_eq!(url,
+            format!(concat!("https://api.openweathermap.org/data/2.5/onecall/timemachine?",
+                            "lat=2.2&lon=1.1&dt=946728000&units=metric&appid={}"),
+                    *OPENWEATHERMAP_AUTHORIZATION))
+    }
+
+    #[tokio::test]
+    pub async fn parse_history_test() {
+        let data = r#"
+        {
+            "current": {"dt": 946728000, "temp": 20.0, "weather": [{"main": "Clear", "description": "clear sky"}]},
+            "hourly": [
+                {"dt": 946681200, "temp": 25.0, "weather": []},
+                {"dt": 946684800, "temp": 18.0, "weather": []},
+                {"dt": 946728000, "temp": 21.0, "weather": []},
+                {"dt": 946767600, "temp": 19.5, "weather": []}
+            ]
+        }"#;
+
+        let date = chrono::NaiveDate::from_ymd(2000, 1, 1);
+        let weather = parse_history(response_with_body(data).await, date).await.unwrap();
+
+        assert_eq!(weather.dt, 946684800);
+        assert_eq!(weather.min_t, 18.0);
+        assert_eq!(weather.max_t, 21.0);
+        assert_eq!(weather.avg_t, 19.5);
+        assert_eq!(weather.condition, "Clear, clear sky");
+
+        let other_date = chrono::NaiveDate::from_ymd(2000, 1, 5);
+        let result = parse_history(response_with_body(data).await, other_date).await;
+        assert_eq!(result.err().unwrap().code(), tonic::Code::OutOfRange);
+    }
+
+    #[tokio::test]
+    pub async fn history_out_of_range_test() {
+        let loc = Location {
+            name: "Name".to_string(),
+            state: "State".to_string(),
+            country: "Country".to_string(),
+            lon: 1.1,
+            lat: 2.2,
+        };
+
+        // The date is rejected before the request is made.
+        let date = chrono::Utc::today().naive_utc() - chrono::Duration::days(HISTORY_DAYS + 1);
+        let result = Integration.get_history(loc, date).await;
+        assert_eq!(result.err().unwrap().code(), tonic::Code::OutOfRange);
+    }
+
+    #[tokio::test]
+    pub async fn malformed_response_test() {
+        for body in ["", "not a json", r#"{"daily": {}}"#, r#"{"daily": [{"dt": "today"}]}"#] {
+            let result = Integration.handle_response(response_with_body(body).await).await;
+            assert_eq!(result.err().unwrap().code(), tonic::Code::Internal);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn empty_condition_test() {
+        let body = r#"{"daily": [{"dt": 946684800, "temp": {"day": 20.0, "min": 19.5, "max": 20.5}, "weather": []}]}"#;
+
+        let forecasts = Integration.handle_response(response_with_body(body).await).await.unwrap();
+        assert_eq!(forecasts.len(), 1);
+        assert_eq!(forecasts[0].condition, "");
+    }
+
     #[tokio::test]
     pub async fn parse_response_test() {
         fn service_mock(server: &MockServer) -> httpmock::Mock {
@@ -1,5 +1,6 @@
 use crate::defs;
 use crate::forecast;
+use crate::forecast::registry::{Capability, ProviderMetadata};
 
 use serde::{Serialize, Deserialize};
 use weather_service_rpc::{Location, WeatherForecast};
@@ -35,16 +36,50 @@ struct Condition {
     text: String,
 }
 
+#[derive(Serialize, Deserialize)]
+struct JSONErrorReply {
+    error: ErrorData,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ErrorData {
+    code: i32,
+    message: String,
+}
+
 lazy_static! {
     static ref WEATHER_API_AUTHORIZATION: String = {
-        return defs::CONFIG.general_section().get("WEATHER_API_AUTHORIZATION").unwrap().to_string();
+        return defs::CONFIG.general_section().get("WEATHER_API_AUTHORIZATION").unwrap_or_default().to_string();
     };
 }
 
+pub const NAME: &str = "WeatherApi";
+
+pub fn metadata() -> ProviderMetadata {
+    ProviderMetadata {
+        capabilities: vec![Capability::Daily, Capability::History],
+        max_forecast_days: 10,
+        units: vec!["metric".to_string()],
+        languages: vec!["en".to_string()],
+        authorization_key: Some("WEATHER_API_AUTHORIZATION".to_string()),
+    }
+}
+
+// The history reply has the same shape as the forecast one, with a single day.
+fn get_history_url(loc: &Location, date: chrono::NaiveDate) -> String {
+    return format!(
+        "http://api.weatherapi.com/v1/history.json?key={}&q={},{}&dt={}",
+        *WEATHER_API_AUTHORIZATION, loc.lat, loc.lon, date.format("%Y-%m-%d"));
+}
+
 // An implementation of ForecastEndService to provide nessesary data and hanle reply from WeatherAPI service.
 pub struct Integration;
 #[tonic::async_trait]
 impl forecast::ForecastEndService for Integration {
+    fn name(&self) -> String {
+        NAME.to_string()
+    }
+
     fn get_url(&self, loc: Location) -> String {
         return format!(
             "http://api.weatherapi.com/v1/forecast.json?key={}&q={},{}&days=10&aqi=no&alerts=no",
@@ -52,11 +87,31 @@ impl forecast::ForecastEndService for Integration {
         );
     }
 
+    // WeatherApi reports some errors with a generic status, so they are recognized by the code in the body.
+    // See https://www.weatherapi.com/docs/#intro-error-codes
+    fn classify_error(&self, status: reqwest::StatusCode, body: &str) -> Option<forecast::Error> {
+        let reply = serde_json::from_str::<JSONErrorReply>(body).ok()?;
+
+        match reply.error.code {
+            1006 => Some(forecast::Error::NotFound { provider: self.name(), description: reply.error.message }),
+            2007 => Some(forecast::Error::QuotaExceeded {
+                provider: self.name(),
+                upstream_status: status.as_u16(),
+                retry_after: None
+            }),
+            1002 | 2006 | 2008 => Some(forecast::Error::Unauthenticated {
+                provider: self.name(),
+                upstream_status: status.as_u16()
+            }),
+            _ => None,
+        }
+    }
+
     async fn handle_response(&self, response: reqwest::Response) -> Result<Vec<WeatherForecast>, forecast::Error> {
         let openweather_reply = response.json::<JSONReply>().await.or_else(
-                |err| return Err(forecast::Error { 
-                    code: forecast::ErrorCode::Internal,
-                    description: format!("Unable to process the response from WeatherApi. {}", err) 
+                |err| return Err(forecast::Error::Internal {
+                    provider: Some(self.name()),
+                    description: format!("Unable to process the response from WeatherApi. {}", err)
                 }))?;
 
         let received_locations: Vec<WeatherForecast> = openweather_reply.forecast.forecastday.into_iter().map(
@@ -72,12 +127,20 @@ impl forecast::ForecastEndService for Integration {
 
         return Ok(received_locations);
     }
+
+    async fn get_history(&self, loc: Location, date: chrono::NaiveDate) -> Result<WeatherForecast, forecast::Error> {
+        let response = forecast::request(self, &get_history_url(&loc, date)).await?;
+
+        forecast::find_forecast(self.handle_response(response).await?, date).ok_or(
+            forecast::Error::DateOutOfRange { provider: self.name(), date: date.format("%m.%d.%Y").to_string() })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::forecast::{Location, ForecastEndService};
+    use crate::forecast::response_with_body;
     use httpmock::prelude::*;
 
     #[test]
@@ -97,6 +160,46 @@ mod tests {
                     *WEATHER_API_AUTHORIZATION))
     }
 
+    #[test]
+    pub fn test_history_url_is_valid() {
+        let loc = Location {
+            name: "Name".to_string(),
+            state: "State".to_string(),
+            country: "Country".to_string(),
+            lon: 1.1,
+            lat: 2.2,
+        };
+
+        let url = get_history_url(&loc, chrono::NaiveDate::from_ymd(2000, 1, 1));
+
+        assert_eq!(url,
+            format!("http://api.weatherapi.com/v1/history.json?key={}&q=2.2,1.1&dt=2000-01-01",
+                    *WEATHER_API_AUTHORIZATION))
+    }
+
+    #[test]
+    pub fn test_classify_error() {
+        let not_found = Integration.classify_error(reqwest::StatusCode::BAD_REQUEST,
+            r#"{"error": {"code": 1006, "message": "No matching location found."}}"#);
+        assert_eq!(not_found.unwrap().code(), tonic::Code::NotFound);
+
+        let quota = Integration.classify_error(reqwest::StatusCode::FORBIDDEN,
+            r#"{"error": {"code": 2007, "message": "API key has exceeded calls per month quota."}}"#);
+        assert_eq!(quota.unwrap().code(), tonic::Code::ResourceExhausted);
+
+        let unknown = Integration.classify_error(reqwest::StatusCode::BAD_REQUEST,
+            r#"{"error": {"code": 9999, "message": "Internal application error."}}"#);
+        assert!(unknown.is_none());
+    }
+
+    #[tokio::test]
+    pub async fn malformed_response_test() {
+        for body in ["", "not a json", r#"{"forecast": []}"#, r#"{"forecast": {"forecastday": [{"date_epoch": 1}]}}"#] {
+            let result = Integration.handle_response(response_with_body(body).await).await;
+            assert_eq!(result.err().unwrap().code(), tonic::Code::Internal);
+        }
+    }
+
     #[tokio::test]
     pub async fn parse_response_test() {
         fn service_mock(server: &MockServer) -> httpmock::Mock {
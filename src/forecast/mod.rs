@@ -1,124 +1,270 @@
-pub mod available_services;
-use available_services::AvailableService;
+pub mod cache;
+pub mod registry;
+pub mod single_flight;
 
 mod forecast_services { 
     pub mod openweathermap; 
     pub mod weatherapi;
+    pub mod openmeteo;
+    pub mod nws;
+    pub mod json_mapping;
 }
-use forecast_services::{openweathermap, weatherapi};
 
-use weather_service_rpc::{Location, WeatherForecast};
-
-#[derive(Debug, PartialEq)]
-pub enum ErrorCode {
-    /// Internal error when performing a request or parsing response.
-    Internal = 1, 
+use crate::archive::{self, Archive};
+use cache::ForecastCache;
+use single_flight::SingleFlight;
+use crate::metrics;
+use crate::upstream;
 
-    /// Client specified an invalid argument.
-    InvalidArgument = 2,
-}
+use lazy_static::lazy_static;
 
-#[derive(Debug)]
-pub struct Error {
-    pub code: ErrorCode,
-    description: String,
-}
+use std::sync::Arc;
 
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "An error occured when performing forecast request: {}.", self.description)
-    }
-}
+use weather_service_rpc::{Location, WeatherForecast};
 
-impl std::error::Error for Error { }
+pub use crate::error::Error;
 
 /// Type for all final weather forecast services.  
 /// Represents an abscract interface that contains a set of methods required 
 /// to execute a weather forecast request by WeatherForecaster. 
 #[tonic::async_trait]
-trait ForecastEndService: Send + Sync {
+pub(crate) trait ForecastEndService: Send + Sync {
+    /// Name of the service used to label its upstream requests.
+    fn name(&self) -> String;
     fn get_url(&self, loc: Location) -> String;
     async fn handle_response(&self, response: reqwest::Response) -> Result<Vec<WeatherForecast>, Error>;
+
+    /// Allows the service to recognize provider-specific error responses, e.g. by an error code in the body.
+    /// Returns None to fall back to the classification by the response status.
+    fn classify_error(&self, _status: reqwest::StatusCode, _body: &str) -> Option<Error> {
+        None
+    }
+
+    /// Returns all forecasts the service has for the location.
+    /// By default performs a single request to the endpoint provided by 'get_url' and handles it by 'handle_response'.
+    /// Services that need several requests to get the forecast override it and perform each of them with 'request'.
+    async fn get_forecasts(&self, loc: Location) -> Result<Vec<WeatherForecast>, Error> {
+        let response = request(self, &self.get_url(loc)).await?;
+
+        self.handle_response(response).await.map_err(|err| {
+            metrics::record_upstream_error(&self.name(), "parse");
+            err
+        })
+    }
+
+    /// Returns the observed weather for the past date in the same shape as the forecast.
+    /// Returns DateOutOfRange by default, i.e. the service has no history.
+    async fn get_history(&self, _loc: Location, date: chrono::NaiveDate) -> Result<WeatherForecast, Error> {
+        Err(Error::DateOutOfRange { provider: self.name(), date: date.format("%m.%d.%Y").to_string() })
+    }
+
+    /// Location for which 'get_url' is requested to check that the service is reachable.
+    fn probe_location(&self) -> Location {
+        Location {
+            name: "Greenwich".to_string(),
+            state: String::new(),
+            country: "GB".to_string(),
+            lon: 0.0,
+            lat: 51.48,
+        }
+    }
+}
+
+/// Performs a request to the url on behalf of the provider and returns the response if it is successful.
+/// Other responses are converted to the error by ForecastEndService::classify_error or by the response status.
+async fn request<S: ForecastEndService + ?Sized>(provider: &S, url_string: &str) -> Result<reqwest::Response, Error> {
+    let url = reqwest::Url::parse(url_string).or_else(|err| Err(Error::Internal {
+        provider: Some(provider.name()),
+        description: format!("There was a problem parsing the url: {}. {}", url_string, err)
+    }))?;
+
+    let response = upstream::get(&provider.name(), url).await.or_else(
+        |err| Err(Error::from_upstream(&provider.name(), err)))?;
+
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let retry_after = crate::error::retry_after(response.headers());
+    let body = response.text().await.unwrap_or_default();
+    tracing::warn!(provider = %provider.name(), %status, body = body.as_str(), "Provider has returned an error.");
+
+    Err(provider.classify_error(status, &body).unwrap_or_else(
+        || Error::from_status(&provider.name(), status, retry_after, &body)))
 }
 
-/// WeatherForecaster makes requests for weather forecasts to the final service from AvailableService enum.
+/// Returns the forecast whose timestamp falls on the date in UTC.
+pub(crate) fn find_forecast(forecasts: Vec<WeatherForecast>, date: chrono::NaiveDate) -> Option<WeatherForecast> {
+    forecasts.into_iter().find(|item| {
+        return chrono::NaiveDateTime::from_timestamp_opt(item.dt, 0)
+            .map_or(false, |date_time| date_time.date() == date);
+    })
+}
+
+/// Upstream requests in flight by the provider name and the location rounded to 0.01 degree.
+type ForecastRequests = SingleFlight<(String, i32, i32), Result<Vec<WeatherForecast>, Error>>;
+
+lazy_static! {
+    static ref IN_FLIGHT: Arc<ForecastRequests> = Arc::new(ForecastRequests::default());
+}
+
+/// WeatherForecaster makes requests for weather forecasts to the final service from the provider registry.
 pub struct WeatherForecaster {
-    provider: Box<dyn ForecastEndService>,
+    provider: Arc<dyn ForecastEndService>,
+    archive: Option<Arc<Archive>>,
+    cache: Option<Arc<ForecastCache>>,
+    in_flight: Arc<ForecastRequests>,
 }
 
-fn make_invalid_date_error() -> Error {
-    Error { 
-        code: ErrorCode::InvalidArgument,
-        description: 
-            concat!("Can't find forecast for specified data.",
-                    "Make sure that you use the format mm.dd.yyyy and do not specify a past date.",).to_string()
+fn make_invalid_date_format_error(date_string: &str) -> Error {
+    Error::InvalidArgument {
+        description: format!("Invalid date '{}'. Make sure that you use the format mm.dd.yyyy", date_string)
     }
 }
 
-/// WeatherForecaster makes requests about weather forecasts to the final service from AvailableService enum.
+/// Stores the forecasts in the archive in the background. Returns the handle of the write, if there is an archive.
+fn archive_forecasts(archive: Option<Arc<Archive>>, provider: String, loc: Location, forecasts: &[WeatherForecast])
+    -> Option<tokio::task::JoinHandle<()>> {
+    let archive = archive?;
+    let forecasts = forecasts.to_vec();
+    let fetched_at = chrono::Utc::now().timestamp();
+
+    Some(tokio::task::spawn_blocking(move || {
+        if let Err(err) = archive.store(&provider, &loc, fetched_at, &forecasts) {
+            tracing::error!(provider = provider.as_str(), error = %err, "Unable to archive the forecasts.");
+        }
+    }))
+}
+
+/// WeatherForecaster makes requests about weather forecasts to the final service from the provider registry.
 impl WeatherForecaster {
-    /// Creates new WeatherForecaster with specified AvailableService.
-    pub fn new(forecast_service: AvailableService) -> Self {
-        Self { provider: get_forecast_integration(forecast_service) }
+    /// Creates new WeatherForecaster for the registered provider with the specified name.
+    /// Returns InvalidArgument if there is no such provider or it is not enabled.
+    pub fn new(provider: &str) -> Result<Self, Error> {
+        let is_enabled = registry::find(provider).map_or(false, |info| info.is_enabled());
+
+        match registry::create(provider) {
+            Some(integration) if is_enabled => Ok(Self {
+                provider: Arc::from(integration),
+                archive: archive::global(),
+                cache: cache::global(),
+                in_flight: IN_FLIGHT.clone(),
+            }),
+            _ => Err(Error::InvalidArgument {
+                description: format!("Weather provider '{}' is unknown or not enabled", provider)
+            }),
+        }
     }
 
-    /// Performs request to the endpoint provided by ForecastEndService::get_url.
-    /// Response is handled by the the same ForecastEndService::handle_response.
+    /// Requests the forecasts by ForecastEndService::get_forecasts and returns the one for the date.
+    /// All received forecasts are stored in the archive in the background.
+    /// The weather for a past date is requested by ForecastEndService::get_history instead and is archived as well.
     /// Accepts a Location struct and date for forecast in the mm.dd.yyyy form.
-    /// At this point, the method returns an error with InvalidArgument code if 
-    /// an unknown location or invalid date is specified.
+    /// Returns InvalidArgument if the date has invalid format and DateOutOfRange
+    /// if the provider has no forecast for it. Errors reported by the provider are classified
+    /// by ForecastEndService::classify_error or by the response status.
     pub async fn get_weather(&self, loc: Location, date_string : String) -> Result<WeatherForecast, Error> {
         let requested_date = 
             chrono::NaiveDate::parse_from_str(&date_string, "%m.%d.%Y").or_else(
-                |_| Err(make_invalid_date_error()))?;
-
-        let url_string = self.provider.get_url(loc);
-        let url = reqwest::Url::parse(&*url_string)
-        .unwrap_or_else(|_| panic!("There was a problem parsing the url: {}", url_string));
-
-        let response = reqwest::Client::new().get(url).send().await.or_else(
-            |err| 
-            Err(Error {
-                code: ErrorCode::Internal,
-                description: format!("Unable to make request. {}", err)
-            }))?;
-
-        match response.status() {
-            reqwest::StatusCode::OK => {
-                let mut forecasts = self.provider.handle_response(response).await?;
-        
-                let found = forecasts.iter().position( |item| {
-                    return chrono::NaiveDateTime::from_timestamp(item.dt, 0).date() == requested_date;
-                });
-        
-                if !found.is_none() {
-                    Ok(forecasts.remove(found.unwrap()))
-                }
-                else {  
-                    Err(make_invalid_date_error())
-                }
+                |_| Err(make_invalid_date_format_error(&date_string)))?;
+
+        if requested_date < chrono::Utc::today().naive_utc() {
+            let weather = self.provider.get_history(loc.clone(), requested_date).await?;
+            archive_forecasts(self.archive.clone(), self.provider.name(), loc, std::slice::from_ref(&weather));
+
+            return Ok(weather);
+        }
+
+        let forecasts = self.get_forecasts(loc).await?;
+
+        find_forecast(forecasts, requested_date).ok_or(
+            Error::DateOutOfRange { provider: self.provider.name(), date: date_string })
+    }
+}
+
+impl WeatherForecaster {
+    /// Returns every forecast the provider has for the location. The forecasts are served from the cache
+    /// if they were fetched less than FORECAST_CACHE_TTL_SECS ago, otherwise they are requested by 'refresh_forecasts'.
+    pub async fn get_forecasts(&self, loc: Location) -> Result<Vec<WeatherForecast>, Error> {
+        if let Some(cache) = &self.cache {
+            let cached = cache.get(&self.provider.name(), &loc);
+            metrics::record_cache_lookup("forecast", cached.is_some());
+
+            if let Some(forecasts) = cached {
+                return Ok(forecasts);
             }
-            reqwest::StatusCode::BAD_REQUEST => {
-                Err(Error {
-                    code: ErrorCode::InvalidArgument,
-                    description: response.text().await.unwrap()
-                })
-            },
-            _ => {
-                Err(Error {
-                    code: ErrorCode::Internal,
-                    description: response.text().await.unwrap()
-                })
+        }
+
+        self.refresh_forecasts(loc).await
+    }
+
+    /// Requests the forecasts from the provider bypassing the cache, puts them into the cache
+    /// and stores them in the archive in the background. Concurrent requests for the same provider
+    /// and location share a single upstream request and receive its result.
+    pub async fn refresh_forecasts(&self, loc: Location) -> Result<Vec<WeatherForecast>, Error> {
+        let provider = self.provider.clone();
+        let archive = self.archive.clone();
+        let cache = self.cache.clone();
+        let key = (provider.name(), (loc.lat * 100.0).round() as i32, (loc.lon * 100.0).round() as i32);
+
+        let (result, joined) = self.in_flight.run(key, async move {
+            let forecasts = provider.get_forecasts(loc.clone()).await?;
+
+            if let Some(cache) = cache {
+                cache.insert(&provider.name(), &loc, forecasts.clone());
             }
+            archive_forecasts(archive, provider.name(), loc, &forecasts);
+
+            Ok(forecasts)
+        }).await;
+
+        if joined {
+            metrics::record_coalesced_request(&self.provider.name());
+        }
+        return result;
+    }
+
+    /// Performs a request to the provider endpoint and checks that it responds successfully.
+    /// Unlike 'get_weather', a rejected API key also counts as unreachable.
+    pub async fn is_reachable(&self) -> bool {
+        let url = match reqwest::Url::parse(&*self.provider.get_url(self.provider.probe_location())) {
+            Ok(url) => url,
+            Err(_) => return false,
+        };
+
+        match upstream::client().get(url).send().await {
+            Ok(response) => response.status().is_success(),
+            Err(_) => false,
         }
     }
 }
 
-fn get_forecast_integration(forecast_type: AvailableService) -> Box<dyn ForecastEndService>  {
-    match forecast_type {
-        AvailableService::OpenWeatherMap => Box::new(openweathermap::Integration),
-        AvailableService::WeatherApi => Box::new(weatherapi::Integration),
+/// Returns the response with the body served by a mock server. The body is read while the server is still running
+/// and the response is rebuilt from it, so the tests don't depend on when the server shuts down.
+#[cfg(test)]
+pub(crate) async fn response_with_body(body: &'static str) -> reqwest::Response {
+    use httpmock::prelude::*;
+
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.method(GET)
+            .path("/forecast");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(body);
+    });
+
+    let url = reqwest::Url::parse(&format!("{}/forecast", server.base_url())).unwrap();
+    let response = reqwest::Client::new().get(url).send().await.unwrap();
+
+    let mut builder = hyper::Response::builder().status(response.status());
+    for (name, value) in response.headers() {
+        builder = builder.header(name, value);
     }
+    let body = response.bytes().await.unwrap();
+
+    reqwest::Response::from(builder.body(body).unwrap())
 }
 
 #[cfg(test)]
@@ -140,6 +286,10 @@ mod tests {
 
     #[tonic::async_trait]
     impl ForecastEndService for StubForecastEndpoint {
+        fn name(&self) -> String {
+            "Stub".to_string()
+        }
+
         fn get_url(&self, _:Location) -> String {
             self.url.clone()
         }
@@ -148,14 +298,14 @@ mod tests {
             if self.ok {
                 Ok(vec![
                     WeatherForecast {
-                        dt: 946684800, // 01.01.2000
+                        dt: date_at(0).and_hms(0, 0, 0).timestamp(),
                         min_t: 19.5,
                         max_t: 20.5,
                         avg_t: 20.0,
                         condition: "Warm and cool".to_string(),
                     },
                     WeatherForecast {
-                        dt: 946771200, // 01.02.2000
+                        dt: date_at(1).and_hms(12, 0, 0).timestamp(),
                         min_t: 21.5,
                         max_t: 22.5,
                         avg_t: 22.0,
@@ -165,9 +315,28 @@ mod tests {
             }
             else
             {
-                Err(forecast::Error{code: forecast::ErrorCode::Internal, description: "An error in stub.".to_string()})
+                Err(forecast::Error::Internal { provider: Some(self.name()), description: "An error in stub.".to_string() })
             }
         }
+
+        async fn get_history(&self, _: Location, date: chrono::NaiveDate) -> Result<WeatherForecast, forecast::Error> {
+            Ok(WeatherForecast {
+                dt: date.and_hms(0, 0, 0).timestamp(),
+                min_t: 9.5,
+                max_t: 10.5,
+                avg_t: 10.0,
+                condition: "Observed".to_string(),
+            })
+        }
+    }
+
+    /// Returns the date that is the number of days from today in UTC.
+    fn date_at(days_from_today: i64) -> chrono::NaiveDate {
+        chrono::Utc::today().naive_utc() + chrono::Duration::days(days_from_today)
+    }
+
+    fn date_string_at(days_from_today: i64) -> String {
+        date_at(days_from_today).format("%m.%d.%Y").to_string()
     }
 
     fn get_any_location() -> Location {
@@ -190,15 +359,15 @@ mod tests {
                         else { reqwest::StatusCode::BAD_REQUEST.as_u16() });
         });
 
-        let stub = Box::new(StubForecastEndpoint::new(format!("{}/forecast", server.base_url()), is_ok));
-        WeatherForecaster { provider: stub }
+        let stub = Arc::new(StubForecastEndpoint::new(format!("{}/forecast", server.base_url()), is_ok));
+        WeatherForecaster { provider: stub, archive: None, cache: None, in_flight: Arc::default() }
     }
 
     #[tokio::test]
     pub async fn test_get_weather_ok_found() {
         let forecaster = create_stub_forecaster(true);
 
-        let result = forecaster.get_weather(get_any_location(), "01.01.2000".to_string()).await;
+        let result = forecaster.get_weather(get_any_location(), date_string_at(0)).await;
         assert!(result.is_ok());
 
         let weather = result.unwrap();
@@ -212,18 +381,132 @@ mod tests {
     pub async fn test_get_weather_ok_not_found() {
         let forecaster = create_stub_forecaster(true);
 
-        let result = forecaster.get_weather(get_any_location(), "01.01.1999".to_string()).await;
+        let result = forecaster.get_weather(get_any_location(), date_string_at(30)).await;
         assert!(result.is_err());
-        assert_eq!(result.err().unwrap().code, forecast::ErrorCode::InvalidArgument);
+        assert_eq!(result.err().unwrap().code(), tonic::Code::OutOfRange);
+    }
+
+    #[tokio::test]
+    pub async fn test_get_weather_ok_next_day() {
+        let forecaster = create_stub_forecaster(true);
+
+        let result = forecaster.get_weather(get_any_location(), date_string_at(1)).await;
+        assert_eq!(result.unwrap().condition, "Warm and cool too");
+    }
+
+    #[tokio::test]
+    pub async fn test_forecasts_are_archived() {
+        let archive = Arc::new(Archive::open_in_memory().unwrap());
+        let forecasts = vec![
+            WeatherForecast { dt: date_at(1).and_hms(0, 0, 0).timestamp(), condition: "Warm".to_string(), ..Default::default() },
+        ];
+
+        let write = archive_forecasts(Some(archive.clone()), "Stub".to_string(), get_any_location(), &forecasts);
+        write.unwrap().await.unwrap();
+
+        let archived = archive.query(&get_any_location(), date_at(1), Some("Stub")).unwrap();
+        assert_eq!(archived.len(), 1);
+        assert_eq!(archived[0].forecast.condition, "Warm");
+
+        assert!(archive_forecasts(None, "Stub".to_string(), get_any_location(), &forecasts).is_none());
+    }
+
+    #[tokio::test]
+    pub async fn test_forecasts_are_cached() {
+        let server = MockServer::start();
+        let endpoint = server.mock(|when, then| {
+            when.method(GET).path("/forecast");
+            then.status(200);
+        });
+
+        let stub = Arc::new(StubForecastEndpoint::new(format!("{}/forecast", server.base_url()), true));
+        let cache = Arc::new(ForecastCache::new(std::time::Duration::from_secs(60)));
+        let forecaster = WeatherForecaster {
+            provider: stub,
+            archive: None,
+            cache: Some(cache.clone()),
+            in_flight: Arc::default(),
+        };
+
+        assert!(forecaster.get_weather(get_any_location(), date_string_at(0)).await.is_ok());
+        assert!(forecaster.get_weather(get_any_location(), date_string_at(1)).await.is_ok());
+        endpoint.assert_hits(1);
+
+        assert!(forecaster.refresh_forecasts(get_any_location()).await.is_ok());
+        endpoint.assert_hits(2);
+        assert_eq!(cache.most_requested(1)[0].count, 2);
+    }
+
+    #[tokio::test]
+    pub async fn test_concurrent_requests_are_coalesced() {
+        let server = MockServer::start();
+        let endpoint = server.mock(|when, then| {
+            when.method(GET).path("/forecast");
+            then.status(200).delay(std::time::Duration::from_millis(100));
+        });
+
+        let stub = Arc::new(StubForecastEndpoint::new(format!("{}/forecast", server.base_url()), true));
+        let forecaster = WeatherForecaster { provider: stub, archive: None, cache: None, in_flight: Arc::default() };
+
+        let (first, second) = futures::join!(
+            forecaster.get_weather(get_any_location(), date_string_at(0)),
+            forecaster.get_weather(get_any_location(), date_string_at(1)));
+
+        assert_eq!(first.unwrap().condition, "Warm and cool");
+        assert_eq!(second.unwrap().condition, "Warm and cool too");
+        endpoint.assert_hits(1);
+
+        // Without a cache the next request goes upstream again.
+        assert!(forecaster.get_weather(get_any_location(), date_string_at(0)).await.is_ok());
+        endpoint.assert_hits(2);
+    }
+
+    #[tokio::test]
+    pub async fn test_trace_context_reaches_provider() {
+        let server = MockServer::start();
+        let endpoint = server.mock(|when, then| {
+            when.method(GET)
+                .path("/forecast")
+                .header_exists("traceparent")
+                .header("tracestate", "vendor=value");
+            then.status(200);
+        });
+
+        let stub = Arc::new(StubForecastEndpoint::new(format!("{}/forecast", server.base_url()), true));
+        let forecaster = WeatherForecaster { provider: stub, archive: None, cache: None, in_flight: Arc::default() };
+
+        let mut metadata = tonic::metadata::MetadataMap::new();
+        metadata.insert("traceparent", "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01".parse().unwrap());
+        metadata.insert("tracestate", "vendor=value".parse().unwrap());
+
+        let result = crate::telemetry::handle_rpc("TestTraceContext", &metadata, async {
+            forecaster.get_weather(get_any_location(), date_string_at(0)).await.map_err(tonic::Status::from)
+        }).await;
+
+        assert!(result.is_ok());
+        endpoint.assert_hits(1);
+    }
+
+    #[tokio::test]
+    pub async fn test_past_date_is_requested_from_history() {
+        // The history is returned even though the forecast endpoint fails.
+        let forecaster = create_stub_forecaster(false);
+
+        let result = forecaster.get_weather(get_any_location(), date_string_at(-3)).await;
+        assert!(result.is_ok());
+
+        let weather = result.unwrap();
+        assert_eq!(weather.dt, date_at(-3).and_hms(0, 0, 0).timestamp());
+        assert_eq!(weather.condition, "Observed");
     }
 
     #[tokio::test]
     pub async fn test_get_weather_err() {
         let forecaster = create_stub_forecaster(false);
 
-        let result = forecaster.get_weather(get_any_location(), "01.01.2000".to_string()).await;
+        let result = forecaster.get_weather(get_any_location(), date_string_at(0)).await;
         assert!(result.is_err());
-        assert_eq!(result.err().unwrap().code, forecast::ErrorCode::InvalidArgument);
+        assert_eq!(result.err().unwrap().code(), tonic::Code::InvalidArgument);
     }
 
     #[tokio::test]
@@ -232,23 +515,52 @@ mod tests {
 
         let result = forecaster.get_weather(get_any_location(), "2000.01.01".to_string()).await;
         assert!(result.is_err());
-        assert_eq!(result.err().unwrap().code, forecast::ErrorCode::InvalidArgument);
+        assert_eq!(result.err().unwrap().code(), tonic::Code::InvalidArgument);
     }
 
     #[tokio::test]
-    #[should_panic]
     pub async fn test_invalid_url() {
-        let stub = Box::new(StubForecastEndpoint::new("this is not an url".to_string(), true));
-        let _ = WeatherForecaster { provider: stub }.
-            get_weather(get_any_location(), "01.01.2000".to_string()).await;
+        let stub = Arc::new(StubForecastEndpoint::new("this is not an url".to_string(), true));
+        let result = WeatherForecaster { provider: stub, archive: None, cache: None, in_flight: Arc::default() }.
+            get_weather(get_any_location(), date_string_at(0)).await;
+        assert!(result.is_err());
+        assert_eq!(result.err().unwrap().code(), tonic::Code::Internal);
+    }
+
+    #[tokio::test]
+    pub async fn test_is_reachable() {
+        assert!(create_stub_forecaster(true).is_reachable().await);
+        assert!(!create_stub_forecaster(false).is_reachable().await);
+    }
+
+    #[tokio::test]
+    pub async fn test_quota_exceeded() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/quota");
+            then.status(429)
+                .header("Retry-After", "120")
+                .body(r#"{"message": "Too many requests"}"#);
+        });
+
+        let stub = Arc::new(StubForecastEndpoint::new(format!("{}/quota", server.base_url()), true));
+        let result = WeatherForecaster { provider: stub, archive: None, cache: None, in_flight: Arc::default() }.
+            get_weather(get_any_location(), date_string_at(0)).await;
+
+        let err = result.err().unwrap();
+        assert_eq!(err.code(), tonic::Code::ResourceExhausted);
+        assert_eq!(err.provider(), Some("Stub"));
+        assert_eq!(err.upstream_status(), Some(429));
+        assert_eq!(err.retry_after(), Some(std::time::Duration::from_secs(120)));
     }
 
     #[tokio::test]
     pub async fn test_cant_make_request() {
-        let stub = Box::new(StubForecastEndpoint::new("http://127.0.0.1:55555".to_string(), true));
-        let result = WeatherForecaster { provider: stub }.
-            get_weather(get_any_location(), "01.01.2000".to_string()).await;
+        let stub = Arc::new(StubForecastEndpoint::new("http://127.0.0.1:55555".to_string(), true));
+        let result = WeatherForecaster { provider: stub, archive: None, cache: None, in_flight: Arc::default() }.
+            get_weather(get_any_location(), date_string_at(0)).await;
         assert!(result.is_err());
-        assert_eq!(result.err().unwrap().code, forecast::ErrorCode::Internal);
+        assert_eq!(result.err().unwrap().code(), tonic::Code::Unavailable);
     }
 }
\ No newline at end of file
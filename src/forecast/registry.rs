@@ -0,0 +1,268 @@
+use crate::defs;
+use super::ForecastEndService;
+use super::forecast_services::{openweathermap, weatherapi, openmeteo, nws, json_mapping};
+
+use lazy_static::lazy_static;
+
+use std::sync::{Arc, RwLock};
+
+/// Kinds of forecasts a provider is able to return. Integrations declare only the kinds they implement,
+/// even if the upstream API offers more.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Capability {
+    Daily,
+    Hourly,
+    Current,
+    Alerts,
+    /// Observed weather for past dates, see ForecastEndService::get_history.
+    History,
+}
+
+/// Description of a provider that doesn't depend on the server configuration.
+#[derive(Clone, Debug)]
+pub struct ProviderMetadata {
+    pub capabilities: Vec<Capability>,
+    /// Number of days, including today, for which the provider has forecasts.
+    pub max_forecast_days: u32,
+    /// Units and languages in which the integration returns the forecasts.
+    pub units: Vec<String>,
+    pub languages: Vec<String>,
+    /// Configuration field that holds the API key. None if the provider can be used without a key.
+    pub authorization_key: Option<String>,
+}
+
+/// Registered provider along with the state of its configuration.
+#[derive(Clone, Debug)]
+pub struct ProviderInfo {
+    pub name: String,
+    pub metadata: ProviderMetadata,
+    pub key_configured: bool,
+}
+
+impl ProviderInfo {
+    /// A provider is enabled if it doesn't require an API key or the key is configured.
+    pub fn is_enabled(&self) -> bool {
+        self.metadata.authorization_key.is_none() || self.key_configured
+    }
+}
+
+/// Names of the providers implemented by the server, which can't be replaced by the configuration.
+const BUILTIN_PROVIDERS: [&str; 4] = [openweathermap::NAME, weatherapi::NAME, openmeteo::NAME, nws::NAME];
+
+type Factory = Box<dyn Fn() -> Box<dyn ForecastEndService> + Send + Sync>;
+
+struct Registration {
+    name: String,
+    metadata: ProviderMetadata,
+    factory: Factory,
+}
+
+impl Registration {
+    fn info(&self) -> ProviderInfo {
+        let key_configured = self.metadata.authorization_key.as_ref().map_or(false, |key| {
+            defs::CONFIG.general_section().get(key).map_or(false, |value| !value.is_empty())
+        });
+
+        ProviderInfo { name: self.name.clone(), metadata: self.metadata.clone(), key_configured }
+    }
+}
+
+/// Providers available to WeatherForecaster by name, in the order of registration.
+#[derive(Default)]
+pub struct ProviderRegistry {
+    providers: Vec<Registration>,
+}
+
+impl ProviderRegistry {
+    /// Creates the registry with the providers implemented by the server.
+    fn with_builtin_providers() -> Self {
+        let mut registry = Self::default();
+
+        registry.register(openweathermap::NAME, openweathermap::metadata(), || Box::new(openweathermap::Integration));
+        registry.register(weatherapi::NAME, weatherapi::metadata(), || Box::new(weatherapi::Integration));
+        registry.register(openmeteo::NAME, openmeteo::metadata(), || Box::new(openmeteo::Integration));
+        registry.register(nws::NAME, nws::metadata(), || Box::new(nws::Integration::default()));
+
+        return registry;
+    }
+
+    /// Adds the provider to the registry. A provider registered under the same name is replaced.
+    pub(crate) fn register(&mut self, name: &str, metadata: ProviderMetadata,
+                           factory: impl Fn() -> Box<dyn ForecastEndService> + Send + Sync + 'static) {
+        let registration = Registration { name: name.to_string(), metadata, factory: Box::new(factory) };
+
+        match self.providers.iter_mut().find(|provider| provider.name == name) {
+            Some(existing) => *existing = registration,
+            None => self.providers.push(registration),
+        }
+    }
+
+    /// Registers the providers defined by the [provider:Name] sections of the configuration.
+    fn register_configured(&mut self, config: &ini::Ini) {
+        for (section_name, section) in config.iter() {
+            let name = match section_name.and_then(|section_name| section_name.strip_prefix(json_mapping::SECTION_PREFIX)) {
+                Some(name) => name,
+                None => continue,
+            };
+
+            if BUILTIN_PROVIDERS.contains(&name) {
+                tracing::error!(provider = name, "The configured provider clashes with a built-in one and is skipped.");
+                continue;
+            }
+
+            match json_mapping::Mapping::from_section(section) {
+                Ok(mapping) => {
+                    tracing::info!(provider = name, "Registering the provider from the configuration.");
+
+                    let mapping = Arc::new(mapping);
+                    self.register(name, json_mapping::metadata(name, &mapping), {
+                        let name = name.to_string();
+                        move || Box::new(json_mapping::Integration::new(&name, mapping.clone()))
+                    });
+                },
+                Err(err) => tracing::error!(provider = name, error = err.as_str(), "Invalid provider configuration."),
+            }
+        }
+    }
+
+    pub fn providers(&self) -> Vec<ProviderInfo> {
+        self.providers.iter().map(Registration::info).collect()
+    }
+
+    pub fn find(&self, name: &str) -> Option<ProviderInfo> {
+        self.providers.iter().find(|provider| provider.name == name).map(Registration::info)
+    }
+
+    pub(crate) fn create(&self, name: &str) -> Option<Box<dyn ForecastEndService>> {
+        self.providers.iter().find(|provider| provider.name == name).map(|provider| (provider.factory)())
+    }
+}
+
+lazy_static! {
+    static ref REGISTRY: RwLock<ProviderRegistry> = RwLock::new(ProviderRegistry::with_builtin_providers());
+}
+
+/// Registers the JSON-mapping providers defined by the [provider:Name] sections of the configuration file.
+/// Sections with an invalid mapping or the name of a built-in provider are skipped.
+pub fn register_configured_providers() {
+    REGISTRY.write().unwrap().register_configured(&defs::CONFIG);
+}
+
+/// Returns all registered providers, including the ones without a configured API key.
+pub fn providers() -> Vec<ProviderInfo> {
+    REGISTRY.read().unwrap().providers()
+}
+
+/// Returns the providers that can be used for forecasts.
+pub fn enabled_providers() -> Vec<ProviderInfo> {
+    providers().into_iter().filter(ProviderInfo::is_enabled).collect()
+}
+
+pub fn find(name: &str) -> Option<ProviderInfo> {
+    REGISTRY.read().unwrap().find(name)
+}
+
+pub(crate) fn create(name: &str) -> Option<Box<dyn ForecastEndService>> {
+    REGISTRY.read().unwrap().create(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use weather_service_rpc::{Location, WeatherForecast};
+
+    struct StubForecastEndpoint;
+
+    #[tonic::async_trait]
+    impl ForecastEndService for StubForecastEndpoint {
+        fn name(&self) -> String {
+            "Stub".to_string()
+        }
+
+        fn get_url(&self, _: Location) -> String {
+            String::new()
+        }
+
+        async fn handle_response(&self, _: reqwest::Response) -> Result<Vec<WeatherForecast>, super::super::Error> {
+            Ok(vec![])
+        }
+    }
+
+    fn metadata(authorization_key: Option<&str>) -> ProviderMetadata {
+        ProviderMetadata {
+            capabilities: vec![Capability::Daily],
+            max_forecast_days: 1,
+            units: vec!["metric".to_string()],
+            languages: vec!["en".to_string()],
+            authorization_key: authorization_key.map(str::to_string),
+        }
+    }
+
+    #[test]
+    pub fn test_register() {
+        let mut registry = ProviderRegistry::default();
+        registry.register("Stub", metadata(None), || Box::new(StubForecastEndpoint));
+        registry.register("Other", metadata(None), || Box::new(StubForecastEndpoint));
+
+        assert_eq!(registry.providers().len(), 2);
+        assert_eq!(registry.create("Stub").unwrap().name(), "Stub");
+        assert!(registry.create("Unknown").is_none());
+    }
+
+    #[test]
+    pub fn test_register_replaces_provider() {
+        let mut registry = ProviderRegistry::default();
+        registry.register("Stub", metadata(None), || Box::new(StubForecastEndpoint));
+        registry.register("Stub", ProviderMetadata { max_forecast_days: 5, ..metadata(None) },
+                          || Box::new(StubForecastEndpoint));
+
+        let providers = registry.providers();
+        assert_eq!(providers.len(), 1);
+        assert_eq!(providers[0].metadata.max_forecast_days, 5);
+    }
+
+    #[test]
+    pub fn test_provider_without_key_is_disabled() {
+        let mut registry = ProviderRegistry::default();
+        registry.register("Keyless", metadata(None), || Box::new(StubForecastEndpoint));
+        registry.register("Keyed", metadata(Some("STUB_MISSING_AUTHORIZATION")), || Box::new(StubForecastEndpoint));
+
+        assert!(registry.find("Keyless").unwrap().is_enabled());
+        assert!(!registry.find("Keyed").unwrap().is_enabled());
+    }
+
+    #[test]
+    pub fn test_configured_provider_does_not_replace_builtin() {
+        let config = ini::Ini::load_from_str(r#"
+            [provider:OpenMeteo]
+            URL_TEMPLATE = http://example.com/forecast?lat={lat}&lon={lon}
+            DAILY_PATH = days
+            TIMESTAMP_FIELD = date
+            MIN_FIELD = min
+            MAX_FIELD = max
+            MAX_FORECAST_DAYS = 3
+
+            [provider:Custom]
+            URL_TEMPLATE = http://example.com/forecast?lat={lat}&lon={lon}
+            DAILY_PATH = days
+            TIMESTAMP_FIELD = date
+            MIN_FIELD = min
+            MAX_FIELD = max
+        "#).unwrap();
+
+        let mut registry = ProviderRegistry::with_builtin_providers();
+        registry.register_configured(&config);
+
+        let names: Vec<String> = registry.providers().into_iter().map(|provider| provider.name).collect();
+        assert_eq!(names, vec!["OpenWeatherMap", "WeatherApi", "OpenMeteo", "NationalWeatherService", "Custom"]);
+        assert_eq!(registry.find("OpenMeteo").unwrap().metadata.max_forecast_days, 7);
+    }
+
+    #[test]
+    pub fn test_builtin_providers() {
+        let names: Vec<String> = providers().into_iter().map(|provider| provider.name).collect();
+
+        assert_eq!(names, vec!["OpenWeatherMap", "WeatherApi", "OpenMeteo", "NationalWeatherService"]);
+        assert!(enabled_providers().iter().any(|provider| provider.name == "OpenMeteo"));
+    }
+}
@@ -0,0 +1,109 @@
+use crate::telemetry;
+
+use futures::future::{BoxFuture, FutureExt, Shared};
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+
+/// Deduplicates concurrent calls with the same key: the first call runs the future,
+/// the calls made while it is in flight wait for it and receive a copy of its result.
+pub struct SingleFlight<K, T: Clone> {
+    in_flight: Arc<Mutex<HashMap<K, Shared<BoxFuture<'static, T>>>>>,
+}
+
+impl<K, T: Clone> Default for SingleFlight<K, T> {
+    fn default() -> Self {
+        SingleFlight { in_flight: Arc::new(Mutex::new(HashMap::new())) }
+    }
+}
+
+impl<K, T> SingleFlight<K, T>
+    where K: Clone + Eq + Hash + Send + 'static,
+          T: Clone + Send + Sync + 'static {
+    /// Returns the result of 'future' or of the call with the same key that is already in flight.
+    /// The second value is true if the call has joined another one.
+    /// The future runs on its own task, so it completes and leaves the registry even if every caller is dropped.
+    /// The task keeps the trace context and the span of the caller that has started it.
+    pub async fn run(&self, key: K, future: impl Future<Output = T> + Send + 'static) -> (T, bool) {
+        let (shared, joined) = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+
+            match in_flight.get(&key) {
+                Some(shared) => (shared.clone(), true),
+                None => {
+                    let registry = self.in_flight.clone();
+                    let cleanup_key = key.clone();
+                    let future = telemetry::propagate(future);
+                    let task = tokio::spawn(async move {
+                        let result = future.await;
+                        // Calls made after this point start a new request, since the result may be outdated.
+                        registry.lock().unwrap().remove(&cleanup_key);
+                        result
+                    });
+
+                    let shared = async move {
+                        task.await.unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()))
+                    }.boxed().shared();
+
+                    in_flight.insert(key, shared.clone());
+                    (shared, false)
+                }
+            }
+        };
+
+        (shared.await, joined)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    pub async fn test_concurrent_calls_are_coalesced() {
+        let flights: Arc<SingleFlight<&str, u32>> = Arc::new(SingleFlight::default());
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let run = |key: &'static str| {
+            let flights = flights.clone();
+            let calls = calls.clone();
+            tokio::spawn(async move {
+                flights.run(key, async move {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    42
+                }).await
+            })
+        };
+
+        let handles: Vec<_> = (0..10).map(|_| run("London")).chain([run("Paris")]).collect();
+        let results = futures::future::join_all(handles).await;
+
+        assert!(results.iter().all(|result| result.as_ref().unwrap().0 == 42));
+        assert_eq!(results.iter().filter(|result| result.as_ref().unwrap().1).count(), 9);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // The finished call is not reused.
+        assert_eq!(flights.run("London", async { 7 }).await, (7, false));
+    }
+
+    #[tokio::test]
+    pub async fn test_cancelled_call_is_removed() {
+        let flights: SingleFlight<&str, u32> = SingleFlight::default();
+
+        let cancelled = tokio::time::timeout(Duration::from_millis(10), flights.run("London", async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            42
+        })).await;
+        assert!(cancelled.is_err());
+
+        // The flight completes without the caller and the next call starts a new one.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(flights.in_flight.lock().unwrap().is_empty());
+        assert_eq!(flights.run("London", async { 7 }).await, (7, false));
+    }
+}
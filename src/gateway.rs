@@ -0,0 +1,229 @@
+use crate::defs;
+use crate::error::{details, Error};
+use crate::json::{forecast_to_json, location_to_json};
+use crate::weather_service_impl::WeatherServiceImpl;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use prost::Message;
+use serde_json::{json, Value};
+use tonic::Code;
+use tonic::metadata::MetadataMap;
+use weather_service_rpc::weather_service_server::WeatherService;
+use weather_service_rpc::{Location, LocationSearchParams, WeatherQueryParams};
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+
+/// Returns the address of the HTTP/JSON gateway. None if the gateway is disabled, which is the default.
+pub fn address() -> Result<Option<SocketAddr>, std::net::AddrParseError> {
+    match defs::CONFIG.general_section().get(defs::GATEWAY_ADDR_KEY).filter(|address| !address.is_empty()) {
+        Some(address) => address.parse().map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Maps the gRPC code to the HTTP status like the Google API HTTP mapping does.
+fn http_status(code: Code) -> StatusCode {
+    match code {
+        Code::Ok => StatusCode::OK,
+        Code::InvalidArgument | Code::OutOfRange | Code::FailedPrecondition => StatusCode::BAD_REQUEST,
+        Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+        Code::PermissionDenied => StatusCode::FORBIDDEN,
+        Code::NotFound => StatusCode::NOT_FOUND,
+        Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+        Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+        Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+        Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn json_response(status: StatusCode, body: &Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// Converts the status of the RPC to the JSON error, e.g.
+/// {"error": {"code": "ResourceExhausted", "message": "...", "reason": "QUOTA_EXCEEDED", "metadata": {...},
+/// "retry_after_secs": 60}}. The reason, the metadata and the retry delay come from the status details
+/// and are omitted if the status has none. The retry delay is also sent in the 'Retry-After' header.
+fn error_response(status: tonic::Status) -> Response<Body> {
+    let mut error = json!({ "code": format!("{:?}", status.code()), "message": status.message() });
+    let mut retry_after = None;
+
+    for detail in details::Status::decode(status.details()).map(|decoded| decoded.details).unwrap_or_default() {
+        match detail.type_url.trim_start_matches("type.googleapis.com/") {
+            "google.rpc.ErrorInfo" => if let Ok(info) = details::ErrorInfo::decode(detail.value.as_slice()) {
+                error["reason"] = json!(info.reason);
+                error["metadata"] = json!(info.metadata);
+            },
+            "google.rpc.RetryInfo" => if let Ok(info) = details::RetryInfo::decode(detail.value.as_slice()) {
+                retry_after = info.retry_delay.map(|delay| delay.seconds);
+            },
+            _ => {},
+        }
+    }
+
+    if let Some(seconds) = retry_after {
+        error["retry_after_secs"] = json!(seconds);
+    }
+
+    let mut response = json_response(http_status(status.code()), &json!({ "error": error }));
+    if let Some(seconds) = retry_after {
+        response.headers_mut().insert(hyper::header::RETRY_AFTER, seconds.into());
+    }
+
+    response
+}
+
+fn make_invalid_argument(description: String) -> tonic::Status {
+    tonic::Status::from(Error::InvalidArgument { description })
+}
+
+fn query_params(request: &Request<Body>) -> HashMap<String, String> {
+    let query = request.uri().query().unwrap_or_default();
+
+    reqwest::Url::parse(&format!("http://gateway/?{}", query))
+        .map(|url| url.query_pairs().into_owned().collect())
+        .unwrap_or_default()
+}
+
+fn parse_coordinate(params: &HashMap<String, String>, name: &str) -> Result<f32, tonic::Status> {
+    params.get(name)
+        .ok_or_else(|| make_invalid_argument(format!("The '{}' parameter is not specified", name)))?
+        .parse::<f32>()
+        .map_err(|_| make_invalid_argument(format!("The '{}' parameter is not a number", name)))
+}
+
+/// Passes the HTTP headers as the metadata of the RPC, so the request id and the tracing headers are kept.
+fn to_rpc_request<T>(message: T, request: &Request<Body>) -> tonic::Request<T> {
+    let mut rpc_request = tonic::Request::new(message);
+    *rpc_request.metadata_mut() = MetadataMap::from_headers(request.headers().clone());
+
+    rpc_request
+}
+
+async fn get_providers(request: &Request<Body>) -> Result<Value, tonic::Status> {
+    let reply = WeatherServiceImpl.get_weather_providers(to_rpc_request((), request)).await?.into_inner();
+
+    Ok(json!({ "providers": reply.providers }))
+}
+
+async fn get_locations(request: &Request<Body>) -> Result<Value, tonic::Status> {
+    let query = query_params(request).remove("query")
+        .ok_or_else(|| make_invalid_argument("The 'query' parameter is not specified".to_string()))?;
+
+    let reply = WeatherServiceImpl.get_locations(to_rpc_request(LocationSearchParams { query }, request)).await?;
+    Ok(json!({ "locations": reply.into_inner().locations.iter().map(location_to_json).collect::<Vec<_>>() }))
+}
+
+async fn get_forecast(request: &Request<Body>) -> Result<Value, tonic::Status> {
+    let mut params = query_params(request);
+    let location = Location {
+        name: String::new(),
+        state: String::new(),
+        country: String::new(),
+        lat: parse_coordinate(&params, "lat")?,
+        lon: parse_coordinate(&params, "lon")?,
+    };
+
+    let query = WeatherQueryParams {
+        provider: params.remove("provider").unwrap_or_default(),
+        location: Some(location),
+        date: params.remove("date").unwrap_or_default(),
+    };
+
+    let reply = WeatherServiceImpl.get_weather(to_rpc_request(query, request)).await?;
+    Ok(forecast_to_json(&reply.into_inner()))
+}
+
+/// Routes the request to the WeatherService RPC with the same name:
+/// GET /v1/providers, GET /v1/locations?query=.. and GET /v1/forecast?lat=..&lon=..&date=mm.dd.yyyy&provider=..
+async fn handle(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let result = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/v1/providers") => get_providers(&request).await,
+        (&Method::GET, "/v1/locations") => get_locations(&request).await,
+        (&Method::GET, "/v1/forecast") => get_forecast(&request).await,
+        (method, path) => Err(tonic::Status::not_found(format!("There is no {} {} endpoint", method, path))),
+    };
+
+    Ok(match result {
+        Ok(body) => json_response(StatusCode::OK, &body),
+        Err(status) => error_response(status),
+    })
+}
+
+/// Serves the HTTP/JSON gateway to the WeatherService until the signal completes.
+pub async fn serve(addr: SocketAddr, signal: impl Future<Output = ()>) -> Result<(), hyper::Error> {
+    let make_service = make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle)) });
+
+    hyper::Server::try_bind(&addr)?.serve(make_service).with_graceful_shutdown(signal).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::forecast::registry;
+
+    use std::time::Duration;
+
+    async fn get(uri: &str) -> (StatusCode, Value) {
+        let response = handle(Request::get(uri).body(Body::empty()).unwrap()).await.unwrap();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    pub async fn test_get_providers() {
+        let (status, body) = get("/v1/providers").await;
+
+        let expected: Vec<String> = registry::enabled_providers().into_iter().map(|provider| provider.name).collect();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({ "providers": expected }));
+    }
+
+    #[tokio::test]
+    pub async fn test_invalid_requests() {
+        let (status, body) = get("/v1/forecast?lat=abc&lon=1.1&date=01.01.2000&provider=OpenMeteo").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["code"], "InvalidArgument");
+        assert_eq!(body["error"]["reason"], "INVALID_ARGUMENT");
+
+        let (status, body) = get("/v1/forecast?lat=2.2&lon=1.1&date=01.01.2000&provider=Unknown").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["reason"], "INVALID_ARGUMENT");
+
+        let (status, _) = get("/v1/locations").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, body) = get("/v1/unknown").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["code"], "NotFound");
+    }
+
+    #[tokio::test]
+    pub async fn test_error_response_keeps_details() {
+        let response = error_response(tonic::Status::from(Error::QuotaExceeded {
+            provider: "Stub".to_string(),
+            upstream_status: 429,
+            retry_after: Some(Duration::from_secs(60)),
+        }));
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[hyper::header::RETRY_AFTER], "60");
+
+        let body: Value = serde_json::from_slice(&hyper::body::to_bytes(response.into_body()).await.unwrap()).unwrap();
+        assert_eq!(body["error"]["reason"], "QUOTA_EXCEEDED");
+        assert_eq!(body["error"]["metadata"]["provider"], "Stub");
+        assert_eq!(body["error"]["retry_after_secs"], 60);
+    }
+}
@@ -0,0 +1,349 @@
+use crate::defs;
+use crate::shutdown;
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use weather_service_rpc::Location;
+
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Maximal number of cached queries. Place names are short, so the cache stays within a few tens of megabytes.
+const DEFAULT_CAPACITY: usize = 100000;
+
+lazy_static! {
+    static ref CACHE: Option<Arc<GeocodingCache>> = {
+        let ttl = ttl();
+        if ttl.is_zero() { None } else { Some(Arc::new(GeocodingCache::new(ttl, negative_ttl()))) }
+    };
+}
+
+fn read_seconds(key: &str, default: u64) -> Duration {
+    let seconds = defs::CONFIG.general_section().get(key)
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(default);
+
+    Duration::from_secs(seconds)
+}
+
+/// Returns the time during which the found locations are served from the cache. Zero disables the cache.
+pub fn ttl() -> Duration {
+    read_seconds(defs::GEOCODING_CACHE_TTL_KEY, 7 * 86400)
+}
+
+/// Returns the time during which the queries without locations are served from the cache.
+/// It is shorter, since a place may be added to the geoservice later.
+pub fn negative_ttl() -> Duration {
+    read_seconds(defs::GEOCODING_NEGATIVE_CACHE_TTL_KEY, 3600)
+}
+
+/// Returns the file where the cache is kept between restarts. None if the cache is kept only in memory.
+pub fn persistence_path() -> Option<PathBuf> {
+    defs::CONFIG.general_section().get(defs::GEOCODING_CACHE_PATH_KEY)
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Folds the case, the whitespace and the punctuation of the query, so 'St. Louis, US' and 'st louis,us'
+/// share an entry. Commas are kept, since they separate the city, the state and the country.
+pub fn normalize(query: &str) -> String {
+    let mut parts: Vec<String> = query.to_lowercase().split(',').map(|part| {
+        part.chars()
+            .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }).collect();
+
+    while parts.last().map_or(false, |part| part.is_empty()) {
+        parts.pop();
+    }
+    parts.join(",")
+}
+
+struct Entry {
+    locations: Vec<Location>,
+    fetched_at: Instant,
+}
+
+/// Query in the insertion queue along with the time its entry was fetched.
+type QueuedQuery = (String, Instant);
+
+/// Entry in the file of the cache. The age is kept as a timestamp, since an 'Instant' doesn't survive a restart.
+#[derive(Serialize, Deserialize)]
+struct StoredEntry {
+    query: String,
+    locations: Vec<StoredLocation>,
+    fetched_at: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredLocation {
+    name: String,
+    state: String,
+    country: String,
+    lat: f32,
+    lon: f32,
+}
+
+/// In-memory cache of the locations found by the geoservice for a normalized query.
+/// Place names don't change, so the results are kept much longer than the forecasts.
+/// Queries without locations are cached as well, so the typos don't keep hitting the geoservice.
+/// At most 'capacity' queries are kept.
+pub struct GeocodingCache {
+    ttl: Duration,
+    negative_ttl: Duration,
+    capacity: usize,
+    /// The entries along with the queries in the order of insertion, so the oldest ones are found without a scan.
+    /// A query stays in the queue after its entry is replaced or removed, such items are skipped.
+    entries: Mutex<(HashMap<String, Entry>, VecDeque<QueuedQuery>)>,
+}
+
+impl GeocodingCache {
+    pub fn new(ttl: Duration, negative_ttl: Duration) -> Self {
+        Self::with_capacity(ttl, negative_ttl, DEFAULT_CAPACITY)
+    }
+
+    pub fn with_capacity(ttl: Duration, negative_ttl: Duration, capacity: usize) -> Self {
+        GeocodingCache { ttl, negative_ttl, capacity, entries: Mutex::new((HashMap::new(), VecDeque::new())) }
+    }
+
+    fn is_fresh(&self, entry: &Entry) -> bool {
+        let ttl = if entry.locations.is_empty() { self.negative_ttl } else { self.ttl };
+        entry.fetched_at.elapsed() < ttl
+    }
+
+    /// Returns the locations if they were found less than TTL ago or an empty vector
+    /// if nothing was found less than the negative TTL ago.
+    pub fn get(&self, query: &str) -> Option<Vec<Location>> {
+        let key = normalize(query);
+        let entries = &mut self.entries.lock().unwrap().0;
+
+        match entries.get(&key) {
+            Some(entry) if self.is_fresh(entry) => Some(entry.locations.clone()),
+            Some(_) => {
+                entries.remove(&key);
+                None
+            },
+            None => None,
+        }
+    }
+
+    /// Caches the locations and evicts the oldest entries that have expired. When the cache is still full,
+    /// the entry fetched first is evicted.
+    pub fn insert(&self, query: &str, locations: Vec<Location>) {
+        self.insert_entry(normalize(query), Entry { locations, fetched_at: Instant::now() });
+    }
+
+    /// Entries must be inserted in the order of 'fetched_at'.
+    fn insert_entry(&self, key: String, entry: Entry) {
+        let mut entries = self.entries.lock().unwrap();
+        let (entries, order) = &mut *entries;
+
+        // Only the oldest entries are checked, so an expired one behind a fresh one waits for a 'get'
+        // or for the eviction, which keeps the insert from scanning the whole cache.
+        while let Some((oldest, fetched_at)) = order.front() {
+            let is_current = entries.get(oldest).map_or(false, |entry| entry.fetched_at == *fetched_at);
+            if is_current && self.is_fresh(&entries[oldest]) {
+                break;
+            }
+            if is_current {
+                entries.remove(oldest);
+            }
+            order.pop_front();
+        }
+
+        if !entries.contains_key(&key) {
+            while entries.len() >= self.capacity {
+                match order.pop_front() {
+                    Some((oldest, fetched_at)) => {
+                        if entries.get(&oldest).map_or(false, |entry| entry.fetched_at == fetched_at) {
+                            entries.remove(&oldest);
+                        }
+                    },
+                    None => break,
+                }
+            }
+        }
+
+        order.push_back((key.clone(), entry.fetched_at));
+        entries.insert(key, entry);
+
+        // The skipped items are dropped once they outnumber the entries, so the queue stays within twice the capacity.
+        if order.len() > 2 * self.capacity.max(entries.len()) {
+            order.retain(|(key, fetched_at)| entries.get(key).map_or(false, |entry| entry.fetched_at == *fetched_at));
+        }
+    }
+
+    /// Writes the entries that have not expired to the file.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let now = SystemTime::now();
+        let stored: Vec<StoredEntry> = self.entries.lock().unwrap().0.iter()
+            .filter(|(_, entry)| self.is_fresh(entry))
+            .map(|(query, entry)| StoredEntry {
+                query: query.clone(),
+                locations: entry.locations.iter().map(|location| StoredLocation {
+                    name: location.name.clone(),
+                    state: location.state.clone(),
+                    country: location.country.clone(),
+                    lat: location.lat,
+                    lon: location.lon,
+                }).collect(),
+                fetched_at: now.checked_sub(entry.fetched_at.elapsed()).unwrap_or(now)
+                    .duration_since(SystemTime::UNIX_EPOCH).map_or(0, |since_epoch| since_epoch.as_secs()),
+            })
+            .collect();
+
+        let json = serde_json::to_string(&stored).map_err(|err| err.to_string())?;
+        std::fs::write(path, json).map_err(|err| err.to_string())
+    }
+
+    /// Adds the entries from the file that have not expired yet. When there are more of them than the capacity,
+    /// the newest ones are kept. Returns the number of added entries.
+    pub fn load(&self, path: &Path) -> Result<usize, String> {
+        let json = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let mut stored: Vec<StoredEntry> = serde_json::from_str(&json).map_err(|err| err.to_string())?;
+        stored.sort_by_key(|stored| stored.fetched_at);
+
+        let now = SystemTime::now();
+        let mut added = 0;
+        for stored in stored {
+            let fetched_at = SystemTime::UNIX_EPOCH + Duration::from_secs(stored.fetched_at);
+            let age = now.duration_since(fetched_at).unwrap_or_default();
+            let entry = match Instant::now().checked_sub(age) {
+                Some(fetched_at) => Entry {
+                    locations: stored.locations.into_iter().map(|location| Location {
+                        name: location.name,
+                        state: location.state,
+                        country: location.country,
+                        lat: location.lat,
+                        lon: location.lon,
+                    }).collect(),
+                    fetched_at,
+                },
+                None => continue,
+            };
+
+            if self.is_fresh(&entry) {
+                self.insert_entry(stored.query, entry);
+                added += 1;
+            }
+        }
+
+        Ok(added)
+    }
+}
+
+/// Returns the cache configured by GEOCODING_CACHE_TTL_SECS. None if the cache is disabled.
+pub fn global() -> Option<Arc<GeocodingCache>> {
+    CACHE.clone()
+}
+
+/// Loads the cache from the file and saves it back before the process exits.
+/// A missing or unreadable file only means that the cache starts empty.
+pub fn persist(cache: Arc<GeocodingCache>, path: PathBuf) {
+    match cache.load(&path) {
+        Ok(count) => tracing::info!(path = %path.display(), count, "Geocoding cache loaded."),
+        Err(err) => tracing::warn!(path = %path.display(), error = err.as_str(), "Unable to load the geocoding cache."),
+    }
+
+    shutdown::register_flush_hook("geocoding cache", move || {
+        if let Err(err) = cache.save(&path) {
+            tracing::error!(path = %path.display(), error = err.as_str(), "Unable to save the geocoding cache.");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_location(name: &str) -> Location {
+        Location {
+            name: name.to_string(),
+            state: "State".to_string(),
+            country: "Country".to_string(),
+            lon: 1.1,
+            lat: 2.2,
+        }
+    }
+
+    #[test]
+    pub fn test_normalize() {
+        assert_eq!(normalize("  St. Louis ,  US "), "st louis,us");
+        assert_eq!(normalize("ST-LOUIS,us,"), "st louis,us");
+        assert_eq!(normalize("London,,GB"), "london,,gb");
+        assert_eq!(normalize("Zürich!"), "zürich");
+    }
+
+    #[test]
+    pub fn test_get_and_expire() {
+        let cache = GeocodingCache::new(Duration::from_millis(1000), Duration::from_millis(200));
+        assert_eq!(cache.get("London"), None);
+
+        cache.insert("London", vec![get_location("London")]);
+        cache.insert("Londno", vec![]);
+        assert_eq!(cache.get("  LONDON."), Some(vec![get_location("London")]));
+        assert_eq!(cache.get("londno"), Some(vec![]));
+        assert_eq!(cache.get("Paris"), None);
+
+        // Queries without locations expire first.
+        std::thread::sleep(Duration::from_millis(500));
+        assert_eq!(cache.get("Londno"), None);
+        assert!(cache.get("London").is_some());
+
+        std::thread::sleep(Duration::from_millis(800));
+        assert_eq!(cache.get("London"), None);
+    }
+
+    #[test]
+    pub fn test_capacity() {
+        let cache = GeocodingCache::with_capacity(Duration::from_millis(500), Duration::from_millis(500), 2);
+        cache.insert("London", vec![get_location("London")]);
+        cache.insert("Paris", vec![get_location("Paris")]);
+        cache.insert("Berlin", vec![get_location("Berlin")]);
+
+        // The entry fetched first is replaced.
+        assert_eq!(cache.entries.lock().unwrap().0.len(), 2);
+        assert_eq!(cache.get("London"), None);
+        assert!(cache.get("Paris").is_some());
+
+        // Replacing an entry keeps a single one for the query, and the queue doesn't grow past twice the capacity.
+        for _ in 0..10 {
+            cache.insert("Paris", vec![get_location("Paris")]);
+        }
+        assert_eq!(cache.entries.lock().unwrap().0.len(), 2);
+        assert!(cache.entries.lock().unwrap().1.len() <= 4);
+        assert!(cache.get("Berlin").is_some());
+
+        // The expired entries are evicted by the next insert.
+        std::thread::sleep(Duration::from_millis(1000));
+        cache.insert("Rome", vec![]);
+        assert_eq!(cache.entries.lock().unwrap().0.len(), 1);
+    }
+
+    #[test]
+    pub fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("geocoding_cache_test_{}.json", std::process::id()));
+
+        let cache = GeocodingCache::new(Duration::from_secs(60), Duration::from_secs(60));
+        cache.insert("London", vec![get_location("London")]);
+        cache.insert("Londno", vec![]);
+        cache.save(&path).unwrap();
+
+        let restored = GeocodingCache::new(Duration::from_secs(60), Duration::from_secs(60));
+        assert_eq!(restored.load(&path).unwrap(), 2);
+        assert_eq!(restored.get("london"), Some(vec![get_location("London")]));
+        assert_eq!(restored.get("Londno"), Some(vec![]));
+
+        // The entries that have expired meanwhile are skipped.
+        let strict = GeocodingCache::new(Duration::from_secs(60), Duration::ZERO);
+        assert_eq!(strict.load(&path).unwrap(), 1);
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(restored.load(&path).is_err());
+    }
+}
@@ -0,0 +1,61 @@
+use crate::defs;
+
+use std::time::Duration;
+
+/// Response headers that the browser lets the gRPC-Web client read, in addition to the default ones.
+/// The error details and the request id are needed to report failures like the native gRPC clients do.
+static EXPOSED_HEADERS: [&str; 2] = ["grpc-status-details-bin", "x-request-id"];
+
+/// How long the browser may cache the result of a CORS preflight request.
+const PREFLIGHT_MAX_AGE: Duration = Duration::from_secs(3600);
+
+/// Origins of the web apps that may call the services from a browser.
+#[derive(Debug, PartialEq)]
+pub enum AllowedOrigins {
+    All,
+    List(Vec<String>),
+}
+
+/// Parses the GRPC_WEB_ALLOWED_ORIGINS value, a comma-separated list of origins, e.g.
+/// 'https://app.example.com,http://localhost:3000'. The '*' value allows any origin.
+pub fn parse_origins(value: &str) -> AllowedOrigins {
+    let origins: Vec<String> = value.split(',').map(str::trim).filter(|origin| !origin.is_empty())
+        .map(|origin| origin.trim_end_matches('/').to_string())
+        .collect();
+
+    if origins.iter().any(|origin| origin == "*") {
+        AllowedOrigins::All
+    } else {
+        AllowedOrigins::List(origins)
+    }
+}
+
+/// Returns the gRPC-Web configuration with the CORS origins from the configuration file.
+/// Without GRPC_WEB_ALLOWED_ORIGINS only the requests without the 'Origin' header are accepted,
+/// i.e. gRPC-Web is available to non-browser clients and proxies, but not to web apps.
+pub fn config() -> tonic_web::Config {
+    let config = tonic_web::config()
+        .expose_headers(EXPOSED_HEADERS)
+        .max_age(PREFLIGHT_MAX_AGE);
+
+    let value = defs::CONFIG.general_section().get(defs::GRPC_WEB_ALLOWED_ORIGINS_KEY).unwrap_or_default();
+    match parse_origins(value) {
+        AllowedOrigins::All => config.allow_all_origins(),
+        AllowedOrigins::List(origins) => config.allow_origins(origins),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_parse_origins() {
+        assert_eq!(parse_origins(""), AllowedOrigins::List(vec![]));
+        assert_eq!(parse_origins(" https://app.example.com/, http://localhost:3000,"), AllowedOrigins::List(vec![
+            "https://app.example.com".to_string(),
+            "http://localhost:3000".to_string(),
+        ]));
+        assert_eq!(parse_origins("https://app.example.com,*"), AllowedOrigins::All);
+    }
+}
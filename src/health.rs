@@ -0,0 +1,152 @@
+use crate::defs;
+use crate::location_search;
+use crate::forecast::WeatherForecaster;
+use crate::forecast::registry::{self, ProviderInfo};
+use crate::weather_service_impl::WeatherServiceImpl;
+
+use std::future::Future;
+use std::time::Duration;
+
+use tonic::transport::NamedService;
+use tonic_health::ServingStatus;
+use tonic_health::server::HealthReporter;
+use weather_service_rpc::LocationSearchParams;
+use weather_service_rpc::weather_service_server::WeatherServiceServer;
+
+/// Encoded file descriptor set of the WeatherService, used by the reflection service.
+pub const WEATHER_SERVICE_FILE_DESCRIPTOR_SET: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/weather_service_descriptor.bin"));
+
+/// Health service name under which the location search status is reported.
+pub static LOCATION_SEARCH_SERVICE_NAME: &str = "LocationSearch";
+
+fn read_seconds(key: &str, default: u64) -> Duration {
+    let seconds = defs::CONFIG.general_section().get(key)
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(default);
+
+    Duration::from_secs(seconds)
+}
+
+fn check_interval() -> Duration {
+    read_seconds(defs::HEALTH_CHECK_INTERVAL_KEY, 300)
+}
+
+fn probe_timeout() -> Duration {
+    read_seconds(defs::HEALTH_CHECK_TIMEOUT_KEY, 10)
+}
+
+fn to_status(is_serving: bool) -> ServingStatus {
+    if is_serving { ServingStatus::Serving } else { ServingStatus::NotServing }
+}
+
+/// Runs the probe and treats the one that doesn't complete in time as failed.
+async fn probe(name: &str, timeout: Duration, check: impl Future<Output = bool>) -> bool {
+    match tokio::time::timeout(timeout, check).await {
+        Ok(is_serving) => is_serving,
+        Err(_) => {
+            tracing::warn!(service = name, "Health probe timed out.");
+            false
+        },
+    }
+}
+
+async fn check_provider(provider: &ProviderInfo) -> bool {
+    // Without the API key the provider rejects requests anyway, so it is not requested at all.
+    match WeatherForecaster::new(&provider.name) {
+        Ok(forecaster) => forecaster.is_reachable().await,
+        Err(_) => false,
+    }
+}
+
+async fn check_location_search() -> bool {
+    if !location_search::is_configured() {
+        return false;
+    }
+
+    // The search goes through the cache and ahead of the batch queries, like the clients' ones, so the probe
+    // neither spends the geoservice quota nor times out while a 'GeocodeBatch' is running.
+    location_search::perform(LocationSearchParams { query: "London".to_string() }).await.is_ok()
+}
+
+/// Reports every service as UNKNOWN, so the health service can answer before the first probes complete.
+pub async fn report_unknown(reporter: &mut HealthReporter) {
+    for provider in registry::providers() {
+        reporter.set_service_status(provider.name, ServingStatus::Unknown).await;
+    }
+
+    reporter.set_service_status(LOCATION_SEARCH_SERVICE_NAME, ServingStatus::Unknown).await;
+    reporter.set_service_status(WeatherServiceServer::<WeatherServiceImpl>::NAME, ServingStatus::Unknown).await;
+}
+
+/// Updates the health status of every forecast provider (by its name), the location search and the WeatherService.
+/// The WeatherService is serving while at least one provider is serving.
+/// The probes run concurrently and each is limited by HEALTH_CHECK_TIMEOUT_SECS.
+pub async fn report_status(reporter: &mut HealthReporter) {
+    let providers = registry::providers();
+    let timeout = probe_timeout();
+    let (provider_statuses, location_search_status) = futures::join!(
+        futures::future::join_all(providers.iter().map(|provider| probe(&provider.name, timeout, check_provider(provider)))),
+        probe(LOCATION_SEARCH_SERVICE_NAME, timeout, check_location_search()),
+    );
+
+    let mut any_provider_serving = false;
+    for (provider, is_serving) in providers.into_iter().zip(provider_statuses) {
+        any_provider_serving |= is_serving;
+        reporter.set_service_status(provider.name, to_status(is_serving)).await;
+    }
+
+    reporter.set_service_status(LOCATION_SEARCH_SERVICE_NAME, to_status(location_search_status)).await;
+
+    if any_provider_serving {
+        reporter.set_serving::<WeatherServiceServer<WeatherServiceImpl>>().await;
+    }
+    else {
+        reporter.set_not_serving::<WeatherServiceServer<WeatherServiceImpl>>().await;
+    }
+}
+
+/// Spawns a task that refreshes the health status right away and then every HEALTH_CHECK_INTERVAL_SECS.
+pub fn spawn_health_checker(mut reporter: HealthReporter) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(check_interval());
+
+        loop {
+            interval.tick().await;
+            report_status(&mut reporter).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+
+    #[test]
+    pub fn test_descriptor_matches_service_name() {
+        let descriptor_set = prost_types::FileDescriptorSet::decode(WEATHER_SERVICE_FILE_DESCRIPTOR_SET).unwrap();
+
+        let service_names: Vec<String> = descriptor_set.file.iter().flat_map(|file| {
+            return file.service.iter().map(move |service| {
+                format!("{}.{}", file.package(), service.name())
+            });
+        }).collect();
+
+        assert!(service_names.contains(&WeatherServiceServer::<WeatherServiceImpl>::NAME.to_string()));
+    }
+
+    #[test]
+    pub fn test_to_status() {
+        assert_eq!(to_status(true), ServingStatus::Serving);
+        assert_eq!(to_status(false), ServingStatus::NotServing);
+    }
+
+    #[tokio::test]
+    pub async fn test_probe_timeout() {
+        let timeout = Duration::from_millis(50);
+        assert!(probe("Stub", timeout, async { true }).await);
+        assert!(!probe("Stub", timeout, async { false }).await);
+        assert!(!probe("Stub", timeout, futures::future::pending()).await);
+    }
+}
@@ -0,0 +1,25 @@
+//! JSON representation of the RPC messages shared by the HTTP/JSON gateway and weather-cli,
+//! so both print the same fields.
+
+use serde_json::{json, Value};
+use weather_service_rpc::{Location, WeatherForecast};
+
+pub fn location_to_json(location: &Location) -> Value {
+    json!({
+        "name": location.name,
+        "state": location.state,
+        "country": location.country,
+        "lat": location.lat,
+        "lon": location.lon,
+    })
+}
+
+pub fn forecast_to_json(forecast: &WeatherForecast) -> Value {
+    json!({
+        "dt": forecast.dt,
+        "min_t": forecast.min_t,
+        "max_t": forecast.max_t,
+        "avg_t": forecast.avg_t,
+        "condition": forecast.condition,
+    })
+}
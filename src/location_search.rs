@@ -1,4 +1,9 @@
 use crate::defs;
+use crate::error::Error;
+use crate::forecast::single_flight::SingleFlight;
+use crate::geocoding_cache;
+use crate::metrics;
+use crate::upstream;
 
 use lazy_static::lazy_static;
 use reqwest;
@@ -6,10 +11,65 @@ use reqwest;
 use serde::{Deserialize};
 use weather_service_rpc::{Location, Locations, LocationSearchParams};
 
+use std::time::Duration;
+
 lazy_static! {
     static ref OPENWEATHERMAP_AUTHORIZATION: String = {
-        return defs::CONFIG.general_section().get("OPENWEATHERMAP_AUTHORIZATION").unwrap().to_string();
+        return defs::CONFIG.general_section().get("OPENWEATHERMAP_AUTHORIZATION").unwrap_or_default().to_string();
     };
+
+    static ref RATE_LIMITER: RateLimiter = RateLimiter::new(Duration::from_secs(60) / requests_per_minute());
+
+    // The priority is a part of the key, so an interactive search doesn't join a batch one waiting in its queue.
+    static ref IN_FLIGHT: SingleFlight<(String, Priority), Result<Vec<Location>, Error>> = SingleFlight::default();
+}
+
+/// Returns the number of requests per minute allowed by the geoservice. The free OpenWeatherMap plan allows 60.
+fn requests_per_minute() -> u32 {
+    defs::CONFIG.general_section().get(defs::GEOCODING_REQUESTS_PER_MINUTE_KEY)
+        .and_then(|value| value.parse::<u32>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(60)
+}
+
+/// Priority of a request to the geoservice.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Priority {
+    /// A search a client is waiting for, e.g. 'GetLocations'.
+    Interactive,
+    /// A query of a batch, let through only one at a time between the interactive ones.
+    Batch,
+}
+
+/// Spaces the requests to the geoservice evenly, the callers of the same priority wait in the order of arrival.
+struct RateLimiter {
+    interval: Duration,
+    next_request: tokio::sync::Mutex<tokio::time::Instant>,
+    /// Held by the batch request waiting for its turn, so the interactive ones queue behind at most one of them.
+    batch_turn: tokio::sync::Mutex<()>,
+}
+
+impl RateLimiter {
+    fn new(interval: Duration) -> Self {
+        RateLimiter {
+            interval,
+            next_request: tokio::sync::Mutex::new(tokio::time::Instant::now()),
+            batch_turn: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Waits until the next request is allowed.
+    async fn acquire(&self, priority: Priority) {
+        let _batch_turn = match priority {
+            Priority::Interactive => None,
+            Priority::Batch => Some(self.batch_turn.lock().await),
+        };
+
+        // The lock is held while sleeping, so the next caller starts waiting only after this one is let through.
+        let mut next_request = self.next_request.lock().await;
+        tokio::time::sleep_until(*next_request).await;
+        *next_request = tokio::time::Instant::now() + self.interval;
+    }
 }
 
 #[derive(Deserialize)]
@@ -21,6 +81,14 @@ struct JSONItem {
     lat: f32,
 }
 
+/// Name under which requests to the geoservice are reported in metrics.
+static GEOCODING_PROVIDER: &str = "OpenWeatherMapGeocoding";
+
+/// Returns true if the API key for the geoservice is configured.
+pub fn is_configured() -> bool {
+    !OPENWEATHERMAP_AUTHORIZATION.is_empty()
+}
+
 async fn parse_response(response: reqwest::Response) -> Result<Vec<Location>, reqwest::Error> {
     let deserialized = response.json::<Vec<JSONItem>>().await?;
     
@@ -37,24 +105,141 @@ async fn parse_response(response: reqwest::Response) -> Result<Vec<Location>, re
 
 /// Function performs a simple 'GET' request from a geoservice. For now 'OpenWeatherMap' is used as the geoservice, 
 /// because it provides simple way to get geolocation by "city,state,country" request.
+/// The requests are spaced to stay within GEOCODING_REQUESTS_PER_MINUTE, the interactive ones go first,
+/// and bypass the cache.
+/// An empty vector is returned if no locations were found for the query.
+/// Returns with an error if it is impossible to perform request, the geoservice responds
+/// with an unsuccessful status or the json-result can't be parsed.
+pub async fn fetch(query: &str, priority: Priority) -> Result<Vec<Location>, Error> {
+    let url = reqwest::Url::parse_with_params(
+        "http://api.openweathermap.org/geo/1.0/direct",
+        &[("q", query), ("limit", "5"), ("appid", OPENWEATHERMAP_AUTHORIZATION.as_str())]
+    ).or_else(|err| Err(Error::Internal {
+        provider: Some(GEOCODING_PROVIDER.to_string()),
+        description: format!("There was a problem building the url. {}", err)
+    }))?;
+
+    RATE_LIMITER.acquire(priority).await;
+
+    let response = upstream::get(GEOCODING_PROVIDER, url).await.or_else(
+        |err| Err(Error::from_upstream(GEOCODING_PROVIDER, err)))?;
+
+    if !response.status().is_success() {
+        return Err(Error::from_response(GEOCODING_PROVIDER, response).await);
+    }
+
+    parse_response(response).await.or_else(|err| Err(Error::Internal {
+        provider: Some(GEOCODING_PROVIDER.to_string()),
+        description: format!("Unable to process the response from the geoservice. {}", err),
+    }))
+}
+
+/// Returns the locations found by the geoservice for the query. The results, including the empty ones, are served
+/// from the geocoding cache, and concurrent searches for the same normalized query share one request.
+/// The batch searches don't share it with the interactive ones, which would wait in the batch queue otherwise.
 /// The 'Locations' structure is returned, which simply contains the 'Location' vector.
 /// An empty vector is returned if no locations were found for the given search parameters.
-/// Returns with an error if it is impossible to perform request or to parse json-result.
-pub async fn perform(search_params: LocationSearchParams) -> Result<Locations, reqwest::Error> {
-    let url_string = format!(
-        "http://api.openweathermap.org/geo/1.0/direct?q={}&limit=5&appid={}",
-        search_params.query,
-        *OPENWEATHERMAP_AUTHORIZATION
-    );
-
-    let url = reqwest::Url::parse(&*url_string)
-        .unwrap_or_else(|_| panic!("There was a problem parsing the url: {}", url_string));
-
-    let response = reqwest::Client::new().get(url).send().await?;
-
-    Ok(Locations {
-        locations: parse_response(response).await?,
-    })
+pub async fn perform(search_params: LocationSearchParams) -> Result<Locations, Error> {
+    search(search_params, Priority::Interactive).await
+}
+
+async fn search(search_params: LocationSearchParams, priority: Priority) -> Result<Locations, Error> {
+    let query = search_params.query;
+    let cache = geocoding_cache::global();
+
+    if let Some(cache) = &cache {
+        let cached = cache.get(&query);
+        metrics::record_cache_lookup("geocoding", cached.is_some());
+
+        if let Some(locations) = cached {
+            return Ok(Locations { locations });
+        }
+    }
+
+    let (locations, _) = IN_FLIGHT.run((geocoding_cache::normalize(&query), priority), async move {
+        let locations = fetch(&query, priority).await?;
+        if let Some(cache) = cache {
+            cache.insert(&query, locations.clone());
+        }
+
+        Ok(locations)
+    }).await;
+
+    Ok(Locations { locations: locations? })
+}
+
+/// The best location found for a query.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match {
+    pub location: Location,
+    /// From 0 to 1, where 1 means that every part of the query matches the location and no other location matches
+    /// as well.
+    pub confidence: f32,
+}
+
+/// Returns how well the location matches the "city,state,country" query, from 0 to 1.
+/// The city counts twice as much as the other parts, which may be either a state or a country.
+fn score(query: &str, location: &Location) -> f32 {
+    let mut parts = query.split(',').map(|part| part.trim().to_lowercase()).filter(|part| !part.is_empty());
+    let city = match parts.next() {
+        Some(city) => city,
+        None => return 0.0,
+    };
+
+    let name = location.name.to_lowercase();
+    let city_score = if name == city {
+        1.0
+    } else if name.contains(&city) || city.contains(&name) {
+        0.5
+    } else {
+        0.0
+    };
+
+    let (mut total, mut weight) = (2.0 * city_score, 2.0);
+    for part in parts {
+        if part == location.state.to_lowercase() || part == location.country.to_lowercase() {
+            total += 1.0;
+        }
+        weight += 1.0;
+    }
+
+    total / weight
+}
+
+/// Locations closer than this are considered the same place found twice, e.g. a city and its district.
+const SAME_PLACE_DEGREES: f32 = 0.5;
+
+/// Returns the location that matches the query best, the first one found by the geoservice on a tie.
+/// The confidence is the score of the location divided by the number of distinct places that score the same,
+/// so an ambiguous query like 'London' gets a low confidence.
+pub fn best_match(query: &str, locations: Vec<Location>) -> Option<Match> {
+    let scores: Vec<f32> = locations.iter().map(|location| score(query, location)).collect();
+
+    let mut best: Option<usize> = None;
+    for (index, score) in scores.iter().enumerate() {
+        if best.map_or(true, |best| *score > scores[best]) {
+            best = Some(index);
+        }
+    }
+    let best = best?;
+
+    let best_location = &locations[best];
+    let ties = locations.iter().zip(&scores)
+        .filter(|(location, score)| **score == scores[best] && (
+            (location.lat - best_location.lat).abs() >= SAME_PLACE_DEGREES ||
+            (location.lon - best_location.lon).abs() >= SAME_PLACE_DEGREES))
+        .count();
+
+    let confidence = scores[best] / (ties + 1) as f32;
+    Some(Match { location: locations.into_iter().nth(best).unwrap(), confidence })
+}
+
+/// Searches for the query like 'perform' does, but with the batch priority, and returns the best match.
+/// None if no locations were found.
+pub async fn geocode(query: &str) -> Result<Option<Match>, Error> {
+    let locations = search(LocationSearchParams { query: query.to_string() }, Priority::Batch).await?.locations;
+
+    Ok(best_match(query, locations))
 }
 
 #[cfg(test)]
@@ -101,6 +286,86 @@ mod tests {
         assert_eq!(locations[0].lat, 1.0);
     }
 
+    #[tokio::test]
+    pub async fn test_parse_malformed_response() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/malformed");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(r#"{"cod": 200, "locations": null}"#);
+        });
+
+        let url = reqwest::Url::parse(&format!("{}/malformed", server.base_url())).unwrap();
+        let response = reqwest::Client::new().get(url).send().await.unwrap();
+
+        assert!(parse_response(response).await.is_err());
+    }
+
+    fn get_location(name: &str, country: &str, lat: f32) -> Location {
+        Location { name: name.to_string(), state: String::new(), country: country.to_string(), lon: 0.0, lat }
+    }
+
+    #[test]
+    pub fn test_best_match() {
+        let locations = vec![
+            get_location("London", "GB", 51.5),
+            get_location("City of London", "GB", 51.51),
+            get_location("London", "CA", 42.98),
+        ];
+
+        let found = best_match("london, ca", locations.clone()).unwrap();
+        assert_eq!(found.location, locations[2]);
+        assert_eq!(found.confidence, 1.0);
+
+        // The city of London is too close to count as another London.
+        let found = best_match("London", locations[..2].to_vec()).unwrap();
+        assert_eq!(found.location, locations[0]);
+        assert_eq!(found.confidence, 1.0);
+
+        let found = best_match("London", locations.clone()).unwrap();
+        assert_eq!(found.location, locations[0]);
+        assert_eq!(found.confidence, 0.5);
+
+        let found = best_match("London,FR", locations[1..2].to_vec()).unwrap();
+        assert_eq!(found.confidence, 1.0 / 3.0);
+
+        assert_eq!(best_match("London", vec![]), None);
+    }
+
+    #[tokio::test]
+    pub async fn test_rate_limiter_spaces_requests() {
+        let limiter = RateLimiter::new(Duration::from_millis(20));
+        let started = std::time::Instant::now();
+
+        futures::future::join_all((0..5).map(|_| limiter.acquire(Priority::Interactive))).await;
+
+        assert!(started.elapsed() >= Duration::from_millis(80));
+    }
+
+    #[tokio::test]
+    pub async fn test_rate_limiter_lets_interactive_requests_first() {
+        let limiter = std::sync::Arc::new(RateLimiter::new(Duration::from_millis(100)));
+        let order = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let request = |priority: Priority| {
+            let (limiter, order) = (limiter.clone(), order.clone());
+            tokio::spawn(async move {
+                limiter.acquire(priority).await;
+                order.lock().unwrap().push(priority);
+            })
+        };
+
+        let mut handles: Vec<_> = (0..3).map(|_| request(Priority::Batch)).collect();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        handles.push(request(Priority::Interactive));
+        futures::future::join_all(handles).await;
+
+        assert_eq!(*order.lock().unwrap(),
+            vec![Priority::Batch, Priority::Batch, Priority::Interactive, Priority::Batch]);
+    }
+
     #[tokio::test]
     pub async fn test_perform_search() {
         let query_string = "London".to_string();
@@ -1,21 +1,117 @@
+mod accuracy;
+mod archive;
+mod batch;
+mod catch_panic;
 mod defs;
+mod error;
+mod extensions_impl;
 mod forecast;
+mod gateway;
+mod geocoding_cache;
+mod grpc_web;
+mod health;
+mod json;
 mod location_search;
+mod metrics;
+mod notifications;
+mod prefetch;
+mod saved_locations;
+mod shutdown;
+mod subscriptions;
+mod telemetry;
+mod tls;
+mod upstream;
+mod weather_server_rpc;
 mod weather_service_impl;
 
+use extensions_impl::WeatherServerExtensionsImpl;
 use weather_service_impl::WeatherServiceImpl;
 
 use tokio;
 use tonic::transport::Server;
+use weather_server_rpc::weather_server_extensions_server::WeatherServerExtensionsServer;
 use weather_service_rpc::weather_service_server::WeatherServiceServer;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let addr: std::net::SocketAddr = 
-        defs::CONFIG.general_section().get(defs::WEATHER_SERVER_ADDR_KEY).unwrap_or("[::1]:50051").parse().unwrap();
-    println!("Running the server on address: {}", addr.to_string());
+    telemetry::init_logging();
+    forecast::registry::register_configured_providers();
 
-    Server::builder().add_service(WeatherServiceServer::new(WeatherServiceImpl{})).serve(addr).await?;
+    let addr: std::net::SocketAddr =
+        defs::CONFIG.general_section().get(defs::WEATHER_SERVER_ADDR_KEY).unwrap_or("[::1]:50051").parse()?;
+
+    let metrics_addr = metrics::address()?;
+    tracing::info!(address = %metrics_addr, "Serving metrics.");
+    tokio::spawn(async move {
+        if let Err(err) = metrics::serve(metrics_addr, shutdown::wait_for_signal()).await {
+            tracing::error!(error = %err, "Metrics endpoint has stopped.");
+        }
+    });
+
+    if let Some(gateway_addr) = gateway::address()? {
+        tracing::info!(address = %gateway_addr, "Serving the HTTP/JSON gateway.");
+        tokio::spawn(async move {
+            if let Err(err) = gateway::serve(gateway_addr, shutdown::wait_for_signal()).await {
+                tracing::error!(error = %err, "HTTP/JSON gateway has stopped.");
+            }
+        });
+    }
+
+    let prefetch_settings = prefetch::Settings::from_config();
+    if let (Some(cache), true) = (forecast::cache::global(), prefetch_settings.is_enabled()) {
+        prefetch::spawn_scheduler(cache, prefetch_settings);
+    }
+
+    if let (Some(cache), Some(path)) = (geocoding_cache::global(), geocoding_cache::persistence_path()) {
+        geocoding_cache::persist(cache, path);
+    }
+
+    if let Some(archive) = archive::global() {
+        archive::spawn_retention(archive.clone());
+        accuracy::spawn_observer(archive);
+    }
+
+    if let Some(saved_locations) = saved_locations::global() {
+        notifications::spawn_scheduler(saved_locations);
+    }
+
+    let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
+    health::report_unknown(&mut health_reporter).await;
+    health::spawn_health_checker(health_reporter);
+
+    let reflection_service = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(health::WEATHER_SERVICE_FILE_DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(weather_server_rpc::FILE_DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(tonic_health::proto::GRPC_HEALTH_V1_FILE_DESCRIPTOR_SET)
+        .build()?;
+
+    // HTTP/1.1 is accepted for the gRPC-Web requests of the browsers, the native clients keep using HTTP/2.
+    let grpc_web = grpc_web::config();
+    let router = Server::builder()
+        .accept_http1(true)
+        .layer(catch_panic::CatchPanicLayer)
+        .add_service(health_service)
+        .add_service(reflection_service)
+        .add_service(grpc_web.enable(WeatherServiceServer::new(WeatherServiceImpl{})))
+        .add_service(grpc_web.enable(WeatherServerExtensionsServer::new(WeatherServerExtensionsImpl{})));
+
+    match tls::TlsSettings::from_config() {
+        Some(settings) => {
+            let acceptor = tls::ReloadableAcceptor::new(settings)?;
+            acceptor.spawn_reloader();
+
+            let listener = tokio::net::TcpListener::bind(addr).await?;
+            tracing::info!(address = %addr, "Running the server with TLS.");
+
+            shutdown::run_with_graceful_shutdown(
+                |signal| router.serve_with_incoming_shutdown(tls::incoming(listener, acceptor), signal)).await?;
+        },
+        None => {
+            tracing::info!(address = %addr, "Running the server.");
+
+            shutdown::run_with_graceful_shutdown(|signal| router.serve_with_shutdown(addr, signal)).await?;
+        }
+    }
 
     Ok(())
-}
\ No newline at end of file
+}
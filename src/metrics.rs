@@ -0,0 +1,198 @@
+use crate::defs;
+
+use lazy_static::lazy_static;
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder};
+
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, StatusCode};
+
+lazy_static! {
+    static ref REGISTRY: Registry = Registry::new();
+
+    static ref RPC_REQUESTS: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("weather_rpc_requests_total", "Number of handled gRPC requests."),
+        &["method", "code"]).unwrap());
+
+    static ref RPC_DURATION: HistogramVec = register(HistogramVec::new(
+        HistogramOpts::new("weather_rpc_duration_seconds", "Time spent handling gRPC requests."),
+        &["method", "code"]).unwrap());
+
+    static ref UPSTREAM_REQUESTS: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("weather_upstream_requests_total", "Number of requests made to upstream providers."),
+        &["provider", "status"]).unwrap());
+
+    static ref UPSTREAM_DURATION: HistogramVec = register(HistogramVec::new(
+        HistogramOpts::new("weather_upstream_duration_seconds", "Time spent waiting for upstream providers."),
+        &["provider"]).unwrap());
+
+    static ref UPSTREAM_ERRORS: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("weather_upstream_errors_total", "Number of failed requests to upstream providers."),
+        &["provider", "kind"]).unwrap());
+
+    static ref UPSTREAM_RETRIES: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("weather_upstream_retries_total", "Number of retried requests to upstream providers."),
+        &["provider"]).unwrap());
+
+    static ref CIRCUIT_BREAKER_STATE: IntGaugeVec = register(IntGaugeVec::new(
+        Opts::new("weather_circuit_breaker_state", "Circuit breaker state per provider: 0 - closed, 1 - half-open, 2 - open."),
+        &["provider"]).unwrap());
+
+    static ref CACHE_LOOKUPS: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("weather_cache_lookups_total", "Number of cache lookups by result (hit or miss)."),
+        &["cache", "result"]).unwrap());
+
+    static ref COALESCED_REQUESTS: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("weather_coalesced_requests_total", "Number of forecast requests that joined an identical request in flight."),
+        &["provider"]).unwrap());
+
+    static ref WEBHOOK_DELIVERIES: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("weather_webhook_deliveries_total", "Number of notifications sent to webhooks by result."),
+        &["result"]).unwrap());
+}
+
+fn register<T: prometheus::core::Collector + Clone + 'static>(collector: T) -> T {
+    REGISTRY.register(Box::new(collector.clone())).unwrap();
+    return collector;
+}
+
+pub enum CircuitBreakerState {
+    Closed = 0,
+    HalfOpen = 1,
+    Open = 2,
+}
+
+/// Awaits the RPC handler and records its count and latency labeled with the method name and the resulting status code.
+pub async fn track_rpc<T>(method: &str, rpc: impl Future<Output = Result<T, tonic::Status>>) -> Result<T, tonic::Status> {
+    let started = Instant::now();
+    let result = rpc.await;
+
+    let code = match &result {
+        Ok(_) => format!("{:?}", tonic::Code::Ok),
+        Err(status) => format!("{:?}", status.code()),
+    };
+
+    RPC_REQUESTS.with_label_values(&[method, &code]).inc();
+    RPC_DURATION.with_label_values(&[method, &code]).observe(started.elapsed().as_secs_f64());
+
+    return result;
+}
+
+/// Records a finished request to an upstream provider.
+/// A request that failed to complete or returned an unsuccessful status is also counted as an error.
+pub fn observe_upstream(provider: &str, started: Instant, result: &Result<reqwest::Response, reqwest::Error>) {
+    UPSTREAM_DURATION.with_label_values(&[provider]).observe(started.elapsed().as_secs_f64());
+
+    match result {
+        Ok(response) => {
+            let status = response.status();
+            UPSTREAM_REQUESTS.with_label_values(&[provider, status.as_str()]).inc();
+
+            if !status.is_success() {
+                record_upstream_error(provider, "status");
+            }
+        },
+        Err(err) => {
+            UPSTREAM_REQUESTS.with_label_values(&[provider, "error"]).inc();
+            record_upstream_error(provider, if err.is_timeout() { "timeout" } else { "connection" });
+        }
+    }
+}
+
+/// Records an upstream error which is not visible from the response status, e.g. an unparsable body.
+pub fn record_upstream_error(provider: &str, kind: &str) {
+    UPSTREAM_ERRORS.with_label_values(&[provider, kind]).inc();
+}
+
+pub fn record_retry(provider: &str) {
+    UPSTREAM_RETRIES.with_label_values(&[provider]).inc();
+}
+
+pub fn set_circuit_breaker_state(provider: &str, state: CircuitBreakerState) {
+    CIRCUIT_BREAKER_STATE.with_label_values(&[provider]).set(state as i64);
+}
+
+/// Records a cache lookup. The hit ratio is calculated from the 'hit' and 'miss' series.
+pub fn record_cache_lookup(cache: &str, hit: bool) {
+    CACHE_LOOKUPS.with_label_values(&[cache, if hit { "hit" } else { "miss" }]).inc();
+}
+
+pub fn record_coalesced_request(provider: &str) {
+    COALESCED_REQUESTS.with_label_values(&[provider]).inc();
+}
+
+pub fn record_webhook_delivery(delivered: bool) {
+    WEBHOOK_DELIVERIES.with_label_values(&[if delivered { "delivered" } else { "failed" }]).inc();
+}
+
+/// Returns all registered metrics in the Prometheus text format.
+pub fn render() -> String {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&REGISTRY.gather(), &mut buffer).unwrap();
+
+    String::from_utf8(buffer).unwrap()
+}
+
+/// Returns the address of the metrics endpoint. By default metrics are served on [::1]:9100.
+pub fn address() -> Result<SocketAddr, std::net::AddrParseError> {
+    defs::CONFIG.general_section().get(defs::METRICS_ADDR_KEY).unwrap_or("[::1]:9100").parse()
+}
+
+async fn handle(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if request.uri().path() != "/metrics" {
+        let mut not_found = Response::new(Body::empty());
+        *not_found.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(not_found);
+    }
+
+    Ok(Response::builder()
+        .header(hyper::header::CONTENT_TYPE, TextEncoder::new().format_type())
+        .body(Body::from(render()))
+        .unwrap())
+}
+
+/// Serves the metrics over HTTP on the '/metrics' path until the signal completes.
+pub async fn serve(addr: SocketAddr, signal: impl Future<Output = ()>) -> Result<(), hyper::Error> {
+    let make_service = make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle)) });
+
+    hyper::Server::try_bind(&addr)?.serve(make_service).with_graceful_shutdown(signal).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    pub async fn test_track_rpc() {
+        let _ = track_rpc("TestMethod", async { Ok::<(), tonic::Status>(()) }).await;
+        let _ = track_rpc("TestMethod", async {
+            Err::<(), tonic::Status>(tonic::Status::invalid_argument("test"))
+        }).await;
+
+        let rendered = render();
+        assert!(rendered.contains(r#"weather_rpc_requests_total{code="Ok",method="TestMethod"} 1"#));
+        assert!(rendered.contains(r#"weather_rpc_requests_total{code="InvalidArgument",method="TestMethod"} 1"#));
+    }
+
+    #[test]
+    pub fn test_cache_lookups() {
+        record_cache_lookup("test", true);
+        record_cache_lookup("test", false);
+        record_cache_lookup("test", true);
+
+        let rendered = render();
+        assert!(rendered.contains(r#"weather_cache_lookups_total{cache="test",result="hit"} 2"#));
+        assert!(rendered.contains(r#"weather_cache_lookups_total{cache="test",result="miss"} 1"#));
+    }
+
+    #[tokio::test]
+    pub async fn test_handle_not_found() {
+        let request = Request::builder().uri("/other").body(Body::empty()).unwrap();
+        let response = handle(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
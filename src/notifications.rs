@@ -0,0 +1,598 @@
+use crate::accuracy;
+use crate::defs;
+use crate::metrics;
+use crate::forecast::WeatherForecaster;
+use crate::saved_locations::{self, SavedLocation, SavedLocations};
+
+use futures::StreamExt;
+use serde_json::json;
+use weather_service_rpc::WeatherForecast;
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Metadata key with the identifier of the notification, which is the same for every attempt of the delivery.
+/// Receivers can use it to drop the duplicates.
+pub static NOTIFICATION_ID_HEADER: &str = "x-notification-id";
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+const WEBHOOK_RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Maximal number of notifications delivered at once, so a slow webhook doesn't delay the others.
+const DELIVERY_CONCURRENCY: usize = 16;
+
+/// Number of days, including today, whose forecasts are checked if the rule doesn't specify it.
+pub const DEFAULT_LOOKAHEAD_DAYS: u32 = 3;
+
+pub const MAX_LOOKAHEAD_DAYS: u32 = 16;
+
+/// Notifications are remembered for this number of days after the date of the forecast.
+const SENT_RETENTION_DAYS: i64 = 7;
+
+fn check_interval() -> Duration {
+    let seconds = defs::CONFIG.general_section().get(defs::NOTIFICATION_CHECK_INTERVAL_KEY)
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(15 * 60);
+
+    Duration::from_secs(seconds)
+}
+
+/// Forecast field checked by a rule.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    MinT,
+    MaxT,
+    AvgT,
+    Condition,
+}
+
+impl Field {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Field::MinT => "min_t",
+            Field::MaxT => "max_t",
+            Field::AvgT => "avg_t",
+            Field::Condition => "condition",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        [Field::MinT, Field::MaxT, Field::AvgT, Field::Condition].into_iter().find(|field| field.as_str() == value)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operator {
+    LessThan,
+    GreaterThan,
+    /// The condition has the category (e.g. 'storm', 'snow', 'rain') or contains the text, ignoring the case.
+    Matches,
+}
+
+impl Operator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operator::LessThan => "less_than",
+            Operator::GreaterThan => "greater_than",
+            Operator::Matches => "matches",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        [Operator::LessThan, Operator::GreaterThan, Operator::Matches].into_iter()
+            .find(|operator| operator.as_str() == value)
+    }
+}
+
+/// Condition over the forecasts for a saved location, e.g. 'min_t less_than 0' for frost
+/// or 'condition matches storm'. Matches are delivered to the webhook once per rule and date.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    pub id: String,
+    pub saved_location_id: String,
+    pub provider: String,
+    pub field: Field,
+    pub operator: Operator,
+    /// Temperature compared by LessThan and GreaterThan.
+    pub threshold: f32,
+    /// Category or text looked up by Matches.
+    pub text: String,
+    pub webhook_url: String,
+    pub lookahead_days: u32,
+}
+
+impl Rule {
+    /// Checks that the operator is applicable to the field and the webhook url is valid.
+    pub fn validate(&self) -> Result<(), String> {
+        match (self.field, self.operator) {
+            (Field::Condition, Operator::Matches) if self.text.trim().is_empty() => {
+                return Err("Text to match is not specified".to_string());
+            },
+            (Field::Condition, Operator::Matches) => {},
+            (Field::Condition, _) => return Err("Condition can only be matched".to_string()),
+            (_, Operator::Matches) => return Err("Temperature can only be compared".to_string()),
+            _ => {},
+        }
+
+        match reqwest::Url::parse(&self.webhook_url) {
+            Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {},
+            _ => return Err(format!("Invalid webhook url '{}'", self.webhook_url)),
+        }
+
+        if self.lookahead_days > MAX_LOOKAHEAD_DAYS {
+            return Err(format!("Lookahead is longer than {} days", MAX_LOOKAHEAD_DAYS));
+        }
+
+        Ok(())
+    }
+
+    pub fn matches(&self, forecast: &WeatherForecast) -> bool {
+        let value = match self.field {
+            Field::MinT => forecast.min_t,
+            Field::MaxT => forecast.max_t,
+            Field::AvgT => forecast.avg_t,
+            Field::Condition => {
+                let text = self.text.trim().to_lowercase();
+                return accuracy::condition_category(&forecast.condition) == text ||
+                       forecast.condition.to_lowercase().contains(&text);
+            },
+        };
+
+        match self.operator {
+            Operator::LessThan => value < self.threshold,
+            Operator::GreaterThan => value > self.threshold,
+            Operator::Matches => false,
+        }
+    }
+}
+
+fn to_date(forecast: &WeatherForecast) -> chrono::NaiveDate {
+    chrono::NaiveDateTime::from_timestamp(forecast.dt, 0).date()
+}
+
+/// Returns the identifier of the notification about the forecast for the date.
+fn notification_id(rule: &Rule, date: chrono::NaiveDate) -> String {
+    format!("{}:{}", rule.id, date.format("%Y-%m-%d"))
+}
+
+fn payload(rule: &Rule, saved: &SavedLocation, forecast: &WeatherForecast) -> serde_json::Value {
+    json!({
+        "id": notification_id(rule, to_date(forecast)),
+        "rule": {
+            "id": rule.id,
+            "field": rule.field.as_str(),
+            "operator": rule.operator.as_str(),
+            "threshold": rule.threshold,
+            "text": rule.text,
+        },
+        "saved_location": {
+            "id": saved.id,
+            "name": saved.name,
+            "lat": saved.location.lat,
+            "lon": saved.location.lon,
+        },
+        "provider": rule.provider,
+        "date": to_date(forecast).format("%Y-%m-%d").to_string(),
+        "forecast": {
+            "dt": forecast.dt,
+            "min_t": forecast.min_t,
+            "max_t": forecast.max_t,
+            "avg_t": forecast.avg_t,
+            "condition": forecast.condition,
+        },
+    })
+}
+
+/// Returns true if the address is reachable from the internet. The webhooks can't point to the loopback,
+/// private, link-local and other special-purpose addresses, so a rule can't be used to probe the internal network.
+fn is_public(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(v4) => {
+            let octets = v4.octets();
+            let is_shared = octets[0] == 100 && (octets[1] & 0xc0) == 64;
+            let is_reserved = octets[0] == 0 || octets[0] >= 240;
+
+            !(v4.is_loopback() || v4.is_private() || v4.is_link_local() || v4.is_broadcast() ||
+              v4.is_documentation() || v4.is_multicast() || is_shared || is_reserved)
+        },
+        IpAddr::V6(v6) => {
+            let segments = v6.segments();
+            // The mapped (::ffff:a.b.c.d), NAT64 (64:ff9b::a.b.c.d) and 6to4 (2002:aabb:ccdd::) addresses
+            // reach the embedded IPv4 one, so they are checked as that address.
+            let embedded = match segments {
+                [0, 0, 0, 0, 0, 0xffff, ..] | [0x64, 0xff9b, 0, 0, 0, 0, ..] => Some((segments[6], segments[7])),
+                [0x2002, high, low, ..] => Some((high, low)),
+                _ => None,
+            };
+            if let Some((high, low)) = embedded {
+                return is_public(IpAddr::V4(std::net::Ipv4Addr::from(((high as u32) << 16) | low as u32)));
+            }
+
+            // The deprecated IPv4-compatible addresses (::a.b.c.d) are rejected along with '::' and '::1'.
+            let is_ipv4_compatible = segments[..6].iter().all(|segment| *segment == 0);
+            let is_unique_local = (segments[0] & 0xfe00) == 0xfc00;
+            let is_link_local = (segments[0] & 0xffc0) == 0xfe80;
+
+            !(is_ipv4_compatible || v6.is_multicast() || is_unique_local || is_link_local)
+        },
+    }
+}
+
+/// Delivers the notifications by 'POST' requests with the JSON payload.
+pub struct Webhook {
+    retries: u32,
+    retry_base_delay: Duration,
+    /// Hosts from NOTIFICATION_WEBHOOK_ALLOWED_HOSTS. If it is not empty, only these hosts are accepted,
+    /// whatever their addresses are, e.g. to deliver the notifications to a service in the internal network.
+    allowed_hosts: Vec<String>,
+    /// Client for every host along with the address it was resolved to, so the connections are reused
+    /// between the deliveries. The client is replaced when the host is resolved to another address.
+    clients: Mutex<HashMap<String, (Option<SocketAddr>, reqwest::Client)>>,
+}
+
+impl Webhook {
+    pub fn from_config() -> Self {
+        let section = defs::CONFIG.general_section();
+        let retries = section.get(defs::NOTIFICATION_WEBHOOK_RETRIES_KEY)
+            .and_then(|value| value.parse::<u32>().ok())
+            .unwrap_or(3);
+        let allowed_hosts = section.get(defs::NOTIFICATION_WEBHOOK_ALLOWED_HOSTS_KEY).unwrap_or_default()
+            .split(',')
+            .map(|host| host.trim().to_lowercase())
+            .filter(|host| !host.is_empty())
+            .collect();
+
+        Webhook::new(retries, WEBHOOK_RETRY_BASE_DELAY, allowed_hosts)
+    }
+
+    fn new(retries: u32, retry_base_delay: Duration, allowed_hosts: Vec<String>) -> Self {
+        Webhook { retries, retry_base_delay, allowed_hosts, clients: Mutex::new(HashMap::new()) }
+    }
+
+    /// Checks that the notifications may be delivered to the url. Returns the host along with the address
+    /// it has been resolved to, so the delivery connects to the checked address even if the DNS answer changes
+    /// meanwhile. None if the host is in the allow-list and is resolved as usual.
+    pub async fn resolve(&self, url: &str) -> Result<Option<(String, SocketAddr)>, String> {
+        let url = reqwest::Url::parse(url).map_err(|_| format!("Invalid webhook url '{}'", url))?;
+        let host = url.host_str().ok_or_else(|| format!("Webhook url '{}' has no host", url))?.to_lowercase();
+
+        if !self.allowed_hosts.is_empty() {
+            return match self.allowed_hosts.contains(&host) {
+                true => Ok(None),
+                false => Err(format!("Webhook host '{}' is not allowed", host)),
+            };
+        }
+
+        let port = url.port_or_known_default().unwrap_or(80);
+        let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host.trim_matches(|c| c == '[' || c == ']'), port))
+            .await
+            .map_err(|err| format!("Unable to resolve the webhook host '{}'. {}", host, err))?
+            .collect();
+
+        match addresses.first() {
+            Some(address) if addresses.iter().all(|address| is_public(address.ip())) => Ok(Some((host, *address))),
+            _ => Err(format!("Webhook host '{}' is not a public address", host)),
+        }
+    }
+
+    fn client(&self, url: &str, resolved: Option<(String, SocketAddr)>) -> Result<reqwest::Client, String> {
+        let (host, address) = match resolved {
+            Some((host, address)) => (host, Some(address)),
+            None => (reqwest::Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_lowercase))
+                .unwrap_or_default(), None),
+        };
+
+        let mut clients = self.clients.lock().unwrap();
+        if let Some((cached_address, client)) = clients.get(&host) {
+            if *cached_address == address {
+                return Ok(client.clone());
+            }
+        }
+
+        // Redirects are not followed, since they could lead to an address that has not been checked.
+        let mut builder = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .timeout(WEBHOOK_TIMEOUT);
+
+        if let Some(address) = address {
+            builder = builder.resolve(&host, address);
+        }
+
+        let client = builder.build().map_err(|err| format!("Unable to create the webhook client. {}", err))?;
+        clients.insert(host, (address, client.clone()));
+        Ok(client)
+    }
+
+    /// Sends the payload to the url if 'resolve' accepts it. Connection errors, 5xx, 408 and 429 responses
+    /// are retried with exponential backoff, the other statuses won't change on a retry.
+    /// Returns the last error if every attempt has failed.
+    pub async fn deliver(&self, url: &str, id: &str, payload: &serde_json::Value) -> Result<(), String> {
+        let client = self.client(url, self.resolve(url).await?)?;
+
+        let mut attempt = 0;
+        loop {
+            let result = client.post(url)
+                .header(NOTIFICATION_ID_HEADER, id)
+                .json(payload)
+                .send().await;
+
+            let (err, is_retryable) = match result {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) => {
+                    let status = response.status();
+                    let is_retryable = status.is_server_error() || status == reqwest::StatusCode::REQUEST_TIMEOUT ||
+                        status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+                    (format!("Webhook has responded with {}", status), is_retryable)
+                },
+                Err(err) => (format!("Unable to call the webhook. {}", err), true),
+            };
+
+            if !is_retryable || attempt >= self.retries {
+                return Err(err);
+            }
+
+            tracing::warn!(url, attempt, error = err.as_str(), "Webhook delivery failed.");
+            attempt += 1;
+            tokio::time::sleep(self.retry_base_delay * 2u32.pow(attempt - 1)).await;
+        }
+    }
+}
+
+/// Evaluates every rule against the forecasts for its saved location and delivers the new matches
+/// concurrently, up to DELIVERY_CONCURRENCY at once. The forecasts for the same provider and location
+/// are requested once per check.
+pub async fn check(storage: Arc<SavedLocations>, webhook: &Webhook) -> Result<(), saved_locations::Error> {
+    let today = chrono::Utc::today().naive_utc();
+    saved_locations::run_blocking(storage.clone(),
+        move |storage| storage.purge_sent_notifications(today - chrono::Duration::days(SENT_RETENTION_DAYS))).await?;
+
+    let rules = saved_locations::run_blocking(storage.clone(), |storage| storage.all_rules()).await?;
+    let mut forecasts: HashMap<(String, String, String), Option<Vec<WeatherForecast>>> = HashMap::new();
+    let mut pending = vec![];
+
+    for (client, rule) in rules {
+        let saved_location_id = rule.saved_location_id.clone();
+        let saved = match saved_locations::run_blocking(storage.clone(),
+            { let client = client.clone(); move |storage| storage.get(&client, &saved_location_id) }).await? {
+            Some(saved) => saved,
+            None => continue,
+        };
+
+        let key = (rule.provider.clone(), client.clone(), saved.id.clone());
+        if !forecasts.contains_key(&key) {
+            let result = match WeatherForecaster::new(&rule.provider) {
+                Ok(forecaster) => forecaster.get_forecasts(saved.location.clone()).await,
+                Err(err) => Err(err),
+            };
+
+            if let Err(err) = &result {
+                tracing::warn!(provider = rule.provider.as_str(), rule = rule.id.as_str(), error = %err,
+                               "Unable to get the forecasts for the notification rule.");
+            }
+            forecasts.insert(key.clone(), result.ok());
+        }
+
+        let lookahead_days = if rule.lookahead_days == 0 { DEFAULT_LOOKAHEAD_DAYS } else { rule.lookahead_days };
+        let until = today + chrono::Duration::days(lookahead_days as i64);
+
+        for forecast in forecasts[&key].iter().flatten() {
+            let date = to_date(forecast);
+            if date < today || date >= until || !rule.matches(forecast) {
+                continue;
+            }
+
+            let rule_id = rule.id.clone();
+            if saved_locations::run_blocking(storage.clone(), move |storage| storage.is_notified(&rule_id, date)).await? {
+                continue;
+            }
+
+            pending.push((rule.clone(), saved.clone(), forecast.clone(), date));
+        }
+    }
+
+    futures::stream::iter(pending).for_each_concurrent(DELIVERY_CONCURRENCY, |(rule, saved, forecast, date)| {
+        notify(storage.clone(), webhook, rule, saved, forecast, date)
+    }).await;
+
+    Ok(())
+}
+
+/// Delivers the notification about the forecast and remembers it, so it is not delivered again.
+async fn notify(storage: Arc<SavedLocations>, webhook: &Webhook, rule: Rule, saved: SavedLocation,
+                forecast: WeatherForecast, date: chrono::NaiveDate) {
+    let id = notification_id(&rule, date);
+    if let Err(err) = webhook.deliver(&rule.webhook_url, &id, &payload(&rule, &saved, &forecast)).await {
+        metrics::record_webhook_delivery(false);
+        tracing::error!(rule = rule.id.as_str(), %date, error = err.as_str(), "Unable to deliver the notification.");
+        return;
+    }
+
+    metrics::record_webhook_delivery(true);
+    tracing::info!(rule = rule.id.as_str(), %date, "Notification delivered.");
+
+    let rule_id = rule.id.clone();
+    if let Err(err) = saved_locations::run_blocking(storage, move |storage| storage.mark_notified(&rule_id, date)).await {
+        tracing::error!(rule = rule.id.as_str(), %date, error = %err, "Unable to remember the delivered notification.");
+    }
+}
+
+/// Spawns a task that checks the notification rules every NOTIFICATION_CHECK_INTERVAL_SECS.
+pub fn spawn_scheduler(storage: Arc<SavedLocations>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let webhook = Webhook::from_config();
+        let mut interval = tokio::time::interval(check_interval());
+
+        loop {
+            interval.tick().await;
+
+            if let Err(err) = check(storage.clone(), &webhook).await {
+                tracing::error!(error = %err, "Unable to check the notification rules.");
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+
+    fn get_rule(field: Field, operator: Operator, threshold: f32, text: &str) -> Rule {
+        Rule {
+            id: "rule".to_string(),
+            saved_location_id: "location".to_string(),
+            provider: "Stub".to_string(),
+            field,
+            operator,
+            threshold,
+            text: text.to_string(),
+            webhook_url: "http://localhost/hook".to_string(),
+            lookahead_days: 0,
+        }
+    }
+
+    /// The mock server listens on the loopback, which is accepted only by the allow-list.
+    fn local_hosts() -> Vec<String> {
+        vec!["127.0.0.1".to_string(), "localhost".to_string()]
+    }
+
+    fn get_forecast(min_t: f32, max_t: f32, condition: &str) -> WeatherForecast {
+        WeatherForecast { dt: 946684800, min_t, max_t, avg_t: (min_t + max_t) / 2.0, condition: condition.to_string() }
+    }
+
+    #[test]
+    pub fn test_rule_matches() {
+        let frost = get_rule(Field::MinT, Operator::LessThan, 0.0, "");
+        let heat = get_rule(Field::MaxT, Operator::GreaterThan, 35.0, "");
+        let storm = get_rule(Field::Condition, Operator::Matches, 0.0, "Storm");
+
+        assert!(frost.matches(&get_forecast(-1.0, 5.0, "Clear")));
+        assert!(!frost.matches(&get_forecast(0.0, 5.0, "Clear")));
+        assert!(heat.matches(&get_forecast(20.0, 36.0, "Sunny")));
+        assert!(!heat.matches(&get_forecast(20.0, 35.0, "Sunny")));
+        assert!(storm.matches(&get_forecast(20.0, 25.0, "Patchy light rain with thunder")));
+        assert!(!storm.matches(&get_forecast(20.0, 25.0, "Rain")));
+    }
+
+    #[test]
+    pub fn test_rule_validation() {
+        assert!(get_rule(Field::MinT, Operator::LessThan, 0.0, "").validate().is_ok());
+        assert!(get_rule(Field::MinT, Operator::Matches, 0.0, "storm").validate().is_err());
+        assert!(get_rule(Field::Condition, Operator::GreaterThan, 0.0, "").validate().is_err());
+        assert!(get_rule(Field::Condition, Operator::Matches, 0.0, " ").validate().is_err());
+        assert!(Rule { webhook_url: "ftp://localhost".to_string(), ..get_rule(Field::MinT, Operator::LessThan, 0.0, "") }
+            .validate().is_err());
+    }
+
+    #[tokio::test]
+    pub async fn test_webhook_delivery() {
+        let server = MockServer::start();
+        let hook = server.mock(|when, then| {
+            when.method(POST)
+                .path("/hook")
+                .header(NOTIFICATION_ID_HEADER, "rule:2000-01-01")
+                .json_body_partial(r#"{"rule": {"field": "min_t"}, "date": "2000-01-01"}"#);
+            then.status(204);
+        });
+
+        let rule = get_rule(Field::MinT, Operator::LessThan, 0.0, "");
+        let saved = SavedLocation {
+            id: "location".to_string(),
+            name: "Site".to_string(),
+            location: weather_service_rpc::Location {
+                name: "Name".to_string(),
+                state: "State".to_string(),
+                country: "Country".to_string(),
+                lon: 1.1,
+                lat: 2.2,
+            },
+        };
+
+        let webhook = Webhook::new(2, Duration::from_millis(1), local_hosts());
+        let payload = payload(&rule, &saved, &get_forecast(-1.0, 5.0, "Clear"));
+        let result = webhook.deliver(&server.url("/hook"), "rule:2000-01-01", &payload).await;
+
+        assert!(result.is_ok());
+        hook.assert_hits(1);
+    }
+
+    #[tokio::test]
+    pub async fn test_webhook_failure_is_retried() {
+        let server = MockServer::start();
+        let hook = server.mock(|when, then| {
+            when.method(POST).path("/hook");
+            then.status(500);
+        });
+
+        let webhook = Webhook::new(2, Duration::from_millis(1), local_hosts());
+        let result = webhook.deliver(&server.url("/hook"), "id", &json!({})).await;
+
+        assert!(result.is_err());
+        hook.assert_hits(3);
+    }
+
+    #[tokio::test]
+    pub async fn test_webhook_client_error_is_not_retried() {
+        let server = MockServer::start();
+        let hook = server.mock(|when, then| {
+            when.method(POST).path("/hook");
+            then.status(410);
+        });
+
+        let webhook = Webhook::new(2, Duration::from_millis(1), local_hosts());
+        let result = webhook.deliver(&server.url("/hook"), "id", &json!({})).await;
+        let _ = webhook.deliver(&server.url("/hook"), "id", &json!({})).await;
+
+        assert!(result.is_err());
+        hook.assert_hits(2);
+        // Both deliveries have used the same client.
+        assert_eq!(webhook.clients.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    pub fn test_is_public() {
+        for address in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1",
+                        "0.0.0.0", "::", "::1", "fe80::1", "fd00::1", "::ffff:127.0.0.1", "::127.0.0.1", "::8.8.8.8",
+                        "64:ff9b::7f00:1", "64:ff9b::a9fe:a9fe", "2002:a00:1::1", "2002:c0a8:101::"] {
+            assert!(!is_public(address.parse().unwrap()), "{}", address);
+        }
+        for address in ["8.8.8.8", "93.184.216.34", "2606:4700:4700::1111", "::ffff:8.8.8.8", "64:ff9b::808:808",
+                        "2002:808:808::1"] {
+            assert!(is_public(address.parse().unwrap()), "{}", address);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_webhook_resolve() {
+        let webhook = Webhook::new(0, Duration::from_millis(1), vec![]);
+        assert!(webhook.resolve("http://127.0.0.1:8080/hook").await.is_err());
+        assert!(webhook.resolve("http://localhost/hook").await.is_err());
+        assert!(webhook.resolve("http://[::1]/hook").await.is_err());
+        assert!(webhook.resolve("http://169.254.169.254/latest/meta-data").await.is_err());
+
+        let resolved = webhook.resolve("https://8.8.8.8/hook").await.unwrap();
+        assert_eq!(resolved, Some(("8.8.8.8".to_string(), "8.8.8.8:443".parse().unwrap())));
+
+        let webhook = Webhook::new(0, Duration::from_millis(1), vec!["localhost".to_string()]);
+        assert_eq!(webhook.resolve("http://LOCALHOST:8080/hook").await, Ok(None));
+        assert!(webhook.resolve("https://8.8.8.8/hook").await.is_err());
+    }
+
+    #[tokio::test]
+    pub async fn test_webhook_to_private_address_is_not_called() {
+        let server = MockServer::start();
+        let hook = server.mock(|when, then| {
+            when.method(POST).path("/hook");
+            then.status(204);
+        });
+
+        let webhook = Webhook::new(2, Duration::from_millis(1), vec![]);
+        let result = webhook.deliver(&server.url("/hook"), "id", &json!({})).await;
+
+        assert!(result.is_err());
+        hook.assert_hits(0);
+    }
+}
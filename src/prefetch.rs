@@ -0,0 +1,181 @@
+use crate::defs;
+use crate::forecast::WeatherForecaster;
+use crate::forecast::cache::ForecastCache;
+
+use rand::Rng;
+use weather_service_rpc::Location;
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Forecasts are refreshed during the last fifth of their TTL.
+const REFRESH_AHEAD_DIVISOR: u32 = 5;
+
+const MIN_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Forecasts requested by the prefetcher.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Target {
+    pub provider: String,
+    pub location: Location,
+}
+
+/// Parses the PREFETCH_LOCATIONS value, e.g. 'OpenMeteo@51.48,0.0;WeatherApi@40.71,-74.01'.
+/// Invalid entries are skipped.
+pub fn parse_locations(value: &str) -> Vec<Target> {
+    value.split(';').map(str::trim).filter(|entry| !entry.is_empty()).filter_map(|entry| {
+        let parsed = entry.split_once('@').and_then(|(provider, coordinates)| {
+            let (lat, lon) = coordinates.split_once(',')?;
+            let location = Location {
+                name: String::new(),
+                state: String::new(),
+                country: String::new(),
+                lat: lat.trim().parse().ok()?,
+                lon: lon.trim().parse().ok()?,
+            };
+
+            Some(Target { provider: provider.trim().to_string(), location })
+        });
+
+        if parsed.is_none() {
+            tracing::error!(entry, "Invalid prefetch location, the format is Provider@lat,lon.");
+        }
+        parsed
+    }).collect()
+}
+
+/// Settings of the prefetcher from the configuration file.
+pub struct Settings {
+    /// Locations that are always kept fresh.
+    pub hot_locations: Vec<Target>,
+    /// Number of the most requested locations that are kept fresh.
+    pub top_locations: usize,
+    /// Maximal number of upstream requests made by the prefetcher per minute.
+    pub requests_per_minute: u32,
+}
+
+impl Settings {
+    pub fn from_config() -> Self {
+        let section = defs::CONFIG.general_section();
+
+        Settings {
+            hot_locations: parse_locations(section.get(defs::PREFETCH_LOCATIONS_KEY).unwrap_or_default()),
+            top_locations: section.get(defs::PREFETCH_TOP_LOCATIONS_KEY)
+                .and_then(|value| value.parse::<usize>().ok())
+                .unwrap_or(10),
+            requests_per_minute: section.get(defs::PREFETCH_REQUESTS_PER_MINUTE_KEY)
+                .and_then(|value| value.parse::<u32>().ok())
+                .filter(|value| *value > 0)
+                .unwrap_or(30),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.hot_locations.is_empty() || self.top_locations > 0
+    }
+}
+
+/// Returns the hot and the most requested locations whose forecasts are not cached or expire within 'refresh_ahead'.
+/// Hot locations go first, since they are refreshed regardless of the traffic.
+pub fn due_for_refresh(cache: &ForecastCache, settings: &Settings, refresh_ahead: Duration) -> Vec<Target> {
+    let most_requested = cache.most_requested(settings.top_locations).into_iter()
+        .map(|request| Target { provider: request.provider, location: request.location });
+
+    let mut seen = HashSet::new();
+    settings.hot_locations.iter().cloned().chain(most_requested)
+        .filter(|target| {
+            seen.insert((target.provider.clone(), (target.location.lat * 100.0).round() as i32,
+                         (target.location.lon * 100.0).round() as i32))
+        })
+        .filter(|target| {
+            cache.expires_in(&target.provider, &target.location).map_or(true, |remaining| remaining < refresh_ahead)
+        })
+        .collect()
+}
+
+async fn refresh(target: Target) {
+    let result = match WeatherForecaster::new(&target.provider) {
+        Ok(forecaster) => forecaster.refresh_forecasts(target.location.clone()).await.map(|_| ()),
+        Err(err) => Err(err),
+    };
+
+    match result {
+        Ok(()) => tracing::debug!(provider = target.provider.as_str(),
+                                  location = %format!("{},{}", target.location.lat, target.location.lon),
+                                  "Forecasts prefetched."),
+        Err(err) => tracing::warn!(provider = target.provider.as_str(), error = %err, "Unable to prefetch forecasts."),
+    }
+}
+
+/// Spawns a task that refreshes the forecasts for the hot and the most requested locations before they expire.
+/// The refreshes are made one by one and spaced evenly to stay within PREFETCH_REQUESTS_PER_MINUTE,
+/// and each check is delayed randomly, so the servers sharing a configuration don't hit the providers at once.
+pub fn spawn_scheduler(cache: Arc<ForecastCache>, settings: Settings) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let refresh_ahead = cache.ttl() / REFRESH_AHEAD_DIVISOR;
+        let check_interval = (refresh_ahead / 2).max(MIN_CHECK_INTERVAL);
+
+        let mut pace = tokio::time::interval(Duration::from_secs(60) / settings.requests_per_minute);
+        pace.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            let jitter = rand::thread_rng().gen_range(0.0..0.5);
+            tokio::time::sleep(check_interval.mul_f64(1.0 + jitter)).await;
+
+            for target in due_for_refresh(&cache, &settings, refresh_ahead) {
+                pace.tick().await;
+                refresh(target).await;
+            }
+
+            cache.decay();
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use weather_service_rpc::WeatherForecast;
+
+    fn get_target(provider: &str, lat: f32) -> Target {
+        Target {
+            provider: provider.to_string(),
+            location: Location { name: String::new(), state: String::new(), country: String::new(), lon: 1.1, lat },
+        }
+    }
+
+    #[test]
+    pub fn test_parse_locations() {
+        let targets = parse_locations("OpenMeteo@2.2,1.1; WeatherApi @ 3.3 , 1.1;invalid;Stub@a,b;");
+
+        assert_eq!(targets, vec![get_target("OpenMeteo", 2.2), get_target("WeatherApi", 3.3)]);
+    }
+
+    #[test]
+    pub fn test_due_for_refresh() {
+        let cache = ForecastCache::new(Duration::from_secs(60));
+        let settings = Settings {
+            hot_locations: vec![get_target("Hot", 2.2), get_target("Fresh", 2.2)],
+            top_locations: 2,
+            requests_per_minute: 30,
+        };
+
+        let forecasts = vec![WeatherForecast { dt: 946684800, min_t: 1.0, max_t: 2.0, avg_t: 1.5, condition: String::new() }];
+        cache.insert("Fresh", &get_target("Fresh", 2.2).location, forecasts);
+
+        for _ in 0..3 {
+            cache.get("Popular", &get_target("Popular", 3.3).location);
+        }
+        cache.get("Hot", &get_target("Hot", 2.2).location);
+        cache.get("Hot", &get_target("Hot", 2.2).location);
+        cache.get("Rare", &get_target("Rare", 4.4).location);
+
+        let due = due_for_refresh(&cache, &settings, Duration::from_secs(10));
+        assert_eq!(due, vec![get_target("Hot", 2.2), get_target("Popular", 3.3)]);
+
+        // The cached forecasts are refreshed once they are close to expiry.
+        let due = due_for_refresh(&cache, &settings, Duration::from_secs(61));
+        assert!(due.contains(&get_target("Fresh", 2.2)));
+    }
+}
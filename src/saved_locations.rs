@@ -0,0 +1,424 @@
+use crate::defs;
+use crate::notifications::{Field, Operator, Rule};
+
+use lazy_static::lazy_static;
+use rand::Rng;
+use ring::digest;
+use rusqlite::{params, Connection, OptionalExtension};
+use tonic::metadata::MetadataMap;
+use tonic::Request;
+use weather_service_rpc::Location;
+
+use std::sync::{Arc, Mutex};
+
+/// Metadata key that identifies the client whose saved locations are requested, if it has no client certificate.
+pub static CLIENT_ID_HEADER: &str = "x-client-id";
+
+/// Maximal length of the client identity and of the name of a saved location.
+const MAX_FIELD_LEN: usize = 256;
+
+lazy_static! {
+    static ref SAVED_LOCATIONS: Option<Arc<SavedLocations>> = open_configured();
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub description: String,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.description)
+    }
+}
+
+impl std::error::Error for Error { }
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Error { description: format!("Saved locations error. {}", err) }
+    }
+}
+
+/// Location saved by a client under its own name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SavedLocation {
+    /// Identifier that doesn't change when the location is renamed.
+    pub id: String,
+    pub name: String,
+    pub location: Location,
+}
+
+/// Returns the identity of the client that sent the request. A client that presented a certificate (mTLS) is
+/// identified by its SHA-256 fingerprint and the 'x-client-id' metadata is ignored. Otherwise the identity is taken
+/// from the metadata, which any client can set to any value, so it is not a security boundary: it only separates
+/// the locations of cooperating clients. Deployments that need to isolate clients should require client certificates
+/// by TLS_CLIENT_CA_PATH.
+pub fn client_id<T>(request: &Request<T>) -> Option<String> {
+    match request.peer_certs().as_ref().and_then(|certs| certs.first()) {
+        Some(cert) => Some(certificate_fingerprint(cert.get_ref())),
+        None => metadata_client_id(request.metadata()),
+    }
+}
+
+fn certificate_fingerprint(der: &[u8]) -> String {
+    let hash = digest::digest(&digest::SHA256, der);
+    let hex: String = hash.as_ref().iter().map(|byte| format!("{:02x}", byte)).collect();
+
+    format!("sha256:{}", hex)
+}
+
+/// Returns the client identity passed in the 'x-client-id' metadata. None if it is missing, empty or too long.
+fn metadata_client_id(metadata: &MetadataMap) -> Option<String> {
+    metadata.get(CLIENT_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty() && value.len() <= MAX_FIELD_LEN)
+        .map(str::to_string)
+}
+
+/// Checks the name of a saved location.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("Name is not specified".to_string());
+    }
+    if name.len() > MAX_FIELD_LEN {
+        return Err(format!("Name is longer than {} bytes", MAX_FIELD_LEN));
+    }
+
+    Ok(())
+}
+
+fn generate_id() -> String {
+    format!("{:016x}", rand::thread_rng().gen::<u64>())
+}
+
+/// SQLite database with the locations saved by the clients.
+pub struct SavedLocations {
+    connection: Mutex<Connection>,
+}
+
+impl SavedLocations {
+    pub fn open(path: &str) -> Result<Self, Error> {
+        Self::init(Connection::open(path)?)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(connection: Connection) -> Result<Self, Error> {
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS saved_locations (
+                client TEXT NOT NULL,
+                id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                location_name TEXT NOT NULL,
+                state TEXT NOT NULL,
+                country TEXT NOT NULL,
+                lat REAL NOT NULL,
+                lon REAL NOT NULL,
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY (client, id)
+            );
+            CREATE TABLE IF NOT EXISTS notification_rules (
+                client TEXT NOT NULL,
+                id TEXT NOT NULL,
+                saved_location_id TEXT NOT NULL,
+                provider TEXT NOT NULL,
+                field TEXT NOT NULL,
+                operator TEXT NOT NULL,
+                threshold REAL NOT NULL,
+                text TEXT NOT NULL,
+                webhook_url TEXT NOT NULL,
+                lookahead_days INTEGER NOT NULL,
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY (client, id)
+            );
+            CREATE TABLE IF NOT EXISTS sent_notifications (
+                rule_id TEXT NOT NULL,
+                date INTEGER NOT NULL,
+                PRIMARY KEY (rule_id, date)
+            );")?;
+
+        Ok(Self { connection: Mutex::new(connection) })
+    }
+
+    pub fn create(&self, client: &str, name: &str, location: Location) -> Result<SavedLocation, Error> {
+        let saved = SavedLocation { id: generate_id(), name: name.to_string(), location };
+        let connection = self.connection.lock().unwrap();
+
+        connection.execute(
+            "INSERT INTO saved_locations (client, id, name, location_name, state, country, lat, lon, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![client, saved.id, saved.name, saved.location.name, saved.location.state, saved.location.country,
+                    saved.location.lat, saved.location.lon, chrono::Utc::now().timestamp()])?;
+
+        Ok(saved)
+    }
+
+    /// Returns the locations of the client in the order they were saved.
+    pub fn list(&self, client: &str) -> Result<Vec<SavedLocation>, Error> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare_cached(
+            "SELECT id, name, location_name, state, country, lat, lon FROM saved_locations
+             WHERE client = ?1 ORDER BY created_at, rowid")?;
+
+        let rows = statement.query_map(params![client], to_saved_location)?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Returns None if the client has no location with the id.
+    pub fn get(&self, client: &str, id: &str) -> Result<Option<SavedLocation>, Error> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare_cached(
+            "SELECT id, name, location_name, state, country, lat, lon FROM saved_locations WHERE client = ?1 AND id = ?2")?;
+
+        Ok(statement.query_row(params![client, id], to_saved_location).optional()?)
+    }
+
+    /// Returns the renamed location or None if the client has no location with the id.
+    pub fn rename(&self, client: &str, id: &str, name: &str) -> Result<Option<SavedLocation>, Error> {
+        let updated = self.connection.lock().unwrap().execute(
+            "UPDATE saved_locations SET name = ?3 WHERE client = ?1 AND id = ?2", params![client, id, name])?;
+
+        if updated == 0 {
+            return Ok(None);
+        }
+        self.get(client, id)
+    }
+
+    /// Removes the location along with its notification rules. Returns false if the client has no location with the id.
+    pub fn delete(&self, client: &str, id: &str) -> Result<bool, Error> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+
+        transaction.execute(
+            "DELETE FROM notification_rules WHERE client = ?1 AND saved_location_id = ?2", params![client, id])?;
+        let deleted = transaction.execute(
+            "DELETE FROM saved_locations WHERE client = ?1 AND id = ?2", params![client, id])?;
+
+        transaction.commit()?;
+        Ok(deleted > 0)
+    }
+
+    /// Stores the rule of the client under a new id, which is returned in the stored rule.
+    pub fn create_rule(&self, client: &str, rule: Rule) -> Result<Rule, Error> {
+        let rule = Rule { id: generate_id(), ..rule };
+        let connection = self.connection.lock().unwrap();
+
+        connection.execute(
+            "INSERT INTO notification_rules (client, id, saved_location_id, provider, field, operator, threshold, text,
+                                             webhook_url, lookahead_days, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![client, rule.id, rule.saved_location_id, rule.provider, rule.field.as_str(), rule.operator.as_str(),
+                    rule.threshold, rule.text, rule.webhook_url, rule.lookahead_days, chrono::Utc::now().timestamp()])?;
+
+        Ok(rule)
+    }
+
+    pub fn list_rules(&self, client: &str) -> Result<Vec<Rule>, Error> {
+        Ok(self.query_rules(Some(client))?.into_iter().map(|(_, rule)| rule).collect())
+    }
+
+    /// Returns the rules of every client along with the client identity.
+    pub fn all_rules(&self) -> Result<Vec<(String, Rule)>, Error> {
+        self.query_rules(None)
+    }
+
+    fn query_rules(&self, client: Option<&str>) -> Result<Vec<(String, Rule)>, Error> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare_cached(
+            "SELECT client, id, saved_location_id, provider, field, operator, threshold, text, webhook_url, lookahead_days
+             FROM notification_rules WHERE ?1 IS NULL OR client = ?1 ORDER BY created_at, rowid")?;
+
+        let rows = statement.query_map(params![client], |row| {
+            let field: String = row.get(4)?;
+            let operator: String = row.get(5)?;
+
+            Ok((row.get(0)?, Rule {
+                id: row.get(1)?,
+                saved_location_id: row.get(2)?,
+                provider: row.get(3)?,
+                field: Field::parse(&field).unwrap_or(Field::Condition),
+                operator: Operator::parse(&operator).unwrap_or(Operator::Matches),
+                threshold: row.get(6)?,
+                text: row.get(7)?,
+                webhook_url: row.get(8)?,
+                lookahead_days: row.get(9)?,
+            }))
+        })?;
+
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Returns false if the client has no rule with the id.
+    pub fn delete_rule(&self, client: &str, id: &str) -> Result<bool, Error> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+
+        let deleted = transaction.execute(
+            "DELETE FROM notification_rules WHERE client = ?1 AND id = ?2", params![client, id])?;
+        if deleted > 0 {
+            transaction.execute("DELETE FROM sent_notifications WHERE rule_id = ?1", params![id])?;
+        }
+
+        transaction.commit()?;
+        Ok(deleted > 0)
+    }
+
+    /// Returns true if the match of the rule for the date has already been delivered.
+    pub fn is_notified(&self, rule_id: &str, date: chrono::NaiveDate) -> Result<bool, Error> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare_cached(
+            "SELECT 1 FROM sent_notifications WHERE rule_id = ?1 AND date = ?2")?;
+
+        Ok(statement.exists(params![rule_id, date.and_hms(0, 0, 0).timestamp()])?)
+    }
+
+    pub fn mark_notified(&self, rule_id: &str, date: chrono::NaiveDate) -> Result<(), Error> {
+        self.connection.lock().unwrap().execute(
+            "INSERT OR IGNORE INTO sent_notifications (rule_id, date) VALUES (?1, ?2)",
+            params![rule_id, date.and_hms(0, 0, 0).timestamp()])?;
+
+        Ok(())
+    }
+
+    /// Forgets the delivered notifications for the dates before 'before'.
+    pub fn purge_sent_notifications(&self, before: chrono::NaiveDate) -> Result<usize, Error> {
+        Ok(self.connection.lock().unwrap().execute(
+            "DELETE FROM sent_notifications WHERE date < ?1", params![before.and_hms(0, 0, 0).timestamp()])?)
+    }
+}
+
+/// Runs the operation on the blocking thread pool, since the SQLite calls block.
+pub async fn run_blocking<T: Send + 'static>(
+    storage: Arc<SavedLocations>,
+    operation: impl FnOnce(&SavedLocations) -> Result<T, Error> + Send + 'static) -> Result<T, Error> {
+    tokio::task::spawn_blocking(move || operation(&storage)).await
+        .unwrap_or_else(|err| Err(Error { description: err.to_string() }))
+}
+
+fn to_saved_location(row: &rusqlite::Row) -> rusqlite::Result<SavedLocation> {
+    Ok(SavedLocation {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        location: Location {
+            name: row.get(2)?,
+            state: row.get(3)?,
+            country: row.get(4)?,
+            lat: row.get(5)?,
+            lon: row.get(6)?,
+        },
+    })
+}
+
+fn open_configured() -> Option<Arc<SavedLocations>> {
+    let path = defs::CONFIG.general_section().get(defs::SAVED_LOCATIONS_PATH_KEY).unwrap_or("saved_locations.sqlite3");
+    if path.is_empty() {
+        return None;
+    }
+
+    match SavedLocations::open(path) {
+        Ok(saved_locations) => Some(Arc::new(saved_locations)),
+        Err(err) => {
+            tracing::error!(path, error = %err, "Unable to open the saved locations.");
+            None
+        }
+    }
+}
+
+/// Returns the storage configured by SAVED_LOCATIONS_PATH. None if it is disabled or can't be opened.
+pub fn global() -> Option<Arc<SavedLocations>> {
+    SAVED_LOCATIONS.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_location(lat: f32) -> Location {
+        Location {
+            name: "Name".to_string(),
+            state: "State".to_string(),
+            country: "Country".to_string(),
+            lon: 1.1,
+            lat,
+        }
+    }
+
+    #[test]
+    pub fn test_saved_locations() {
+        let saved_locations = SavedLocations::open_in_memory().unwrap();
+
+        let home = saved_locations.create("client", "Home", get_location(2.2)).unwrap();
+        let work = saved_locations.create("client", "Work", get_location(3.3)).unwrap();
+        saved_locations.create("other", "Home", get_location(4.4)).unwrap();
+        assert_ne!(home.id, work.id);
+
+        assert_eq!(saved_locations.list("client").unwrap(), vec![home.clone(), work.clone()]);
+        assert_eq!(saved_locations.get("client", &home.id).unwrap(), Some(home.clone()));
+        assert_eq!(saved_locations.get("other", &home.id).unwrap(), None);
+
+        let renamed = saved_locations.rename("client", &home.id, "Cottage").unwrap().unwrap();
+        assert_eq!(renamed, SavedLocation { name: "Cottage".to_string(), ..home.clone() });
+        assert_eq!(saved_locations.rename("other", &work.id, "Office").unwrap(), None);
+
+        assert!(saved_locations.delete("client", &work.id).unwrap());
+        assert!(!saved_locations.delete("client", &work.id).unwrap());
+        assert_eq!(saved_locations.list("client").unwrap(), vec![renamed]);
+        assert_eq!(saved_locations.list("other").unwrap().len(), 1);
+    }
+
+    #[test]
+    pub fn test_notification_rules() {
+        let saved_locations = SavedLocations::open_in_memory().unwrap();
+        let site = saved_locations.create("client", "Site", get_location(2.2)).unwrap();
+
+        let rule = Rule {
+            id: String::new(),
+            saved_location_id: site.id.clone(),
+            provider: "OpenMeteo".to_string(),
+            field: Field::MinT,
+            operator: Operator::LessThan,
+            threshold: 0.0,
+            text: String::new(),
+            webhook_url: "http://localhost/hook".to_string(),
+            lookahead_days: 3,
+        };
+        let frost = saved_locations.create_rule("client", rule.clone()).unwrap();
+        let heat = saved_locations.create_rule("client", Rule { field: Field::MaxT, ..rule }).unwrap();
+
+        assert_eq!(saved_locations.list_rules("client").unwrap(), vec![frost.clone(), heat.clone()]);
+        assert!(saved_locations.list_rules("other").unwrap().is_empty());
+        assert_eq!(saved_locations.all_rules().unwrap()[0], ("client".to_string(), frost.clone()));
+
+        let date = chrono::NaiveDate::from_ymd(2000, 1, 1);
+        assert!(!saved_locations.is_notified(&frost.id, date).unwrap());
+        saved_locations.mark_notified(&frost.id, date).unwrap();
+        saved_locations.mark_notified(&frost.id, date).unwrap();
+        assert!(saved_locations.is_notified(&frost.id, date).unwrap());
+        assert_eq!(saved_locations.purge_sent_notifications(date.succ()).unwrap(), 1);
+
+        assert!(!saved_locations.delete_rule("other", &frost.id).unwrap());
+        assert!(saved_locations.delete_rule("client", &frost.id).unwrap());
+        assert!(saved_locations.delete("client", &site.id).unwrap());
+        assert!(saved_locations.list_rules("client").unwrap().is_empty());
+    }
+
+    #[test]
+    pub fn test_client_id() {
+        let mut request = Request::new(());
+        assert_eq!(client_id(&request), None);
+
+        request.metadata_mut().insert(CLIENT_ID_HEADER, " client-1 ".parse().unwrap());
+        assert_eq!(client_id(&request), Some("client-1".to_string()));
+
+        assert_eq!(certificate_fingerprint(b"abc"),
+                   "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+
+        assert!(validate_name("Home").is_ok());
+        assert!(validate_name("  ").is_err());
+    }
+}
@@ -0,0 +1,164 @@
+use crate::defs;
+
+use lazy_static::lazy_static;
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::Duration;
+
+type FlushHook = Box<dyn Fn() + Send>;
+
+lazy_static! {
+    static ref FLUSH_HOOKS: Mutex<Vec<(String, FlushHook)>> = Mutex::new(Vec::new());
+}
+
+/// Signal future passed to the server. Completes when the server should stop accepting new connections.
+pub type ShutdownSignal = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Returns the time during which in-flight RPCs may complete after a shutdown signal.
+pub fn drain_period() -> Duration {
+    let seconds = defs::CONFIG.general_section().get(defs::SHUTDOWN_DRAIN_PERIOD_KEY)
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(30);
+
+    Duration::from_secs(seconds)
+}
+
+/// Registers a function that is called once before the process exits.
+/// Subsystems that keep caches or counters in memory use it to save them to disk.
+pub fn register_flush_hook(name: &str, hook: impl Fn() + Send + 'static) {
+    FLUSH_HOOKS.lock().unwrap().push((name.to_string(), Box::new(hook)));
+}
+
+/// Calls all registered flush hooks in the order of registration.
+pub fn flush_all() {
+    for (name, hook) in FLUSH_HOOKS.lock().unwrap().iter() {
+        tracing::info!(name = name.as_str(), "Flushing state before exit.");
+        hook();
+    }
+}
+
+/// Completes when the process receives SIGINT or SIGTERM.
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate()).expect("Unable to listen for SIGTERM.");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {},
+            _ = terminate.recv() => {},
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+/// Runs the server created by 'serve' until SIGINT or SIGTERM is received.
+/// After the signal the server stops accepting new connections and in-flight RPCs
+/// have 'drain_period' to complete. Flush hooks are called before returning.
+pub async fn run_with_graceful_shutdown<F, Fut, E>(serve: F) -> Result<(), E>
+where
+    F: FnOnce(ShutdownSignal) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+{
+    run_until(serve, wait_for_signal(), drain_period()).await
+}
+
+async fn run_until<F, Fut, E>(serve: F, signal: impl Future<Output = ()> + Send + 'static, drain_period: Duration)
+    -> Result<(), E>
+where
+    F: FnOnce(ShutdownSignal) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+{
+    let (signaled_sender, signaled_receiver) = tokio::sync::oneshot::channel();
+
+    let server = serve(Box::pin(async move {
+        signal.await;
+        tracing::info!(?drain_period, "Shutdown signal received, draining in-flight requests.");
+        let _ = signaled_sender.send(());
+    }));
+    tokio::pin!(server);
+
+    let result = tokio::select! {
+        biased;
+
+        result = &mut server => result,
+        Ok(()) = signaled_receiver => {
+            match tokio::time::timeout(drain_period, &mut server).await {
+                Ok(result) => result,
+                Err(_) => {
+                    tracing::warn!("Drain period has elapsed, remaining requests are cancelled.");
+                    Ok(())
+                }
+            }
+        }
+    };
+
+    flush_all();
+
+    return result;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    pub fn test_flush_hooks_are_called() {
+        let counter = Arc::new(AtomicUsize::new(0));
+
+        let hook_counter = counter.clone();
+        register_flush_hook("test counter", move || { hook_counter.fetch_add(1, Ordering::SeqCst); });
+
+        flush_all();
+        assert!(counter.load(Ordering::SeqCst) >= 1);
+    }
+
+    #[tokio::test]
+    pub async fn test_server_finishes_without_signal() {
+        let result: Result<(), ()> = run_until(
+            |_| async { Ok(()) },
+            std::future::pending(),
+            Duration::from_secs(1)).await;
+
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    pub async fn test_in_flight_requests_complete() {
+        let result: Result<(), &str> = run_until(
+            |signal| async move {
+                signal.await;
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                Err("drained")
+            },
+            async {},
+            Duration::from_secs(5)).await;
+
+        assert_eq!(result, Err("drained"));
+    }
+
+    #[tokio::test]
+    pub async fn test_drain_period_elapsed() {
+        let started = std::time::Instant::now();
+
+        let result: Result<(), ()> = run_until(
+            |signal| async move {
+                signal.await;
+                std::future::pending::<()>().await;
+                Ok(())
+            },
+            async {},
+            Duration::from_millis(50)).await;
+
+        assert!(result.is_ok());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,264 @@
+use crate::defs;
+
+use futures::Stream;
+use lazy_static::lazy_static;
+use prost::bytes::Bytes;
+use tokio::sync::watch;
+use tonic::{Code, Status};
+use weather_service_rpc::{Location, WeatherForecast};
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+lazy_static! {
+    static ref HUB: Arc<Hub> = Hub::new(poll_interval());
+}
+
+fn poll_interval() -> Duration {
+    let seconds = defs::CONFIG.general_section().get(defs::SUBSCRIPTION_POLL_INTERVAL_KEY)
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(300);
+
+    Duration::from_secs(seconds)
+}
+
+/// Forecasts polled for the same provider, date and location (rounded to 0.01 degree) are shared by the subscribers.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Key {
+    provider: String,
+    lat: i32,
+    lon: i32,
+    date: String,
+}
+
+impl Key {
+    pub fn new(provider: &str, location: &Location, date: &str) -> Self {
+        Key {
+            provider: provider.to_string(),
+            lat: (location.lat * 100.0).round() as i32,
+            lon: (location.lon * 100.0).round() as i32,
+            date: date.to_string(),
+        }
+    }
+}
+
+/// Status of the first request, which is passed to the subscribers since tonic::Status can't be cloned.
+#[derive(Clone, Debug)]
+struct Failure {
+    code: Code,
+    message: String,
+    details: Bytes,
+}
+
+impl From<&Status> for Failure {
+    fn from(status: &Status) -> Self {
+        Failure {
+            code: status.code(),
+            message: status.message().to_string(),
+            details: Bytes::copy_from_slice(status.details()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Update {
+    /// The first request hasn't completed yet.
+    Pending,
+    Forecast(WeatherForecast),
+    /// The first request has failed. The poller is stopped.
+    Failed(Failure),
+}
+
+/// Background pollers shared by the subscribers of the same key.
+/// A poller is started by the first subscriber and stops when the last one goes away.
+pub struct Hub {
+    pollers: Mutex<HashMap<Key, Arc<watch::Sender<Update>>>>,
+    interval: Duration,
+}
+
+impl Hub {
+    pub fn new(interval: Duration) -> Arc<Self> {
+        Arc::new(Hub { pollers: Mutex::new(HashMap::new()), interval })
+    }
+
+    /// Returns the stream of forecasts for the key. The first item is the current forecast, the following ones
+    /// are sent only when the forecast changes. If the first request fails, the stream ends with its status.
+    /// Later failures are logged and the previous forecast is kept.
+    pub fn subscribe<F, Fut>(self: &Arc<Self>, key: Key, fetch: F) -> impl Stream<Item = Result<WeatherForecast, Status>>
+        where F: Fn() -> Fut + Send + 'static,
+              Fut: Future<Output = Result<WeatherForecast, Status>> + Send + 'static {
+        let mut pollers = self.pollers.lock().unwrap();
+
+        let receiver = match pollers.get(&key) {
+            Some(sender) => sender.subscribe(),
+            None => {
+                let (sender, receiver) = watch::channel(Update::Pending);
+                let sender = Arc::new(sender);
+                pollers.insert(key.clone(), sender.clone());
+
+                tokio::spawn(self.clone().poll(key, sender, fetch));
+                receiver
+            }
+        };
+
+        to_stream(receiver)
+    }
+
+    async fn poll<F, Fut>(self: Arc<Self>, key: Key, sender: Arc<watch::Sender<Update>>, fetch: F)
+        where F: Fn() -> Fut,
+              Fut: Future<Output = Result<WeatherForecast, Status>> {
+        let mut interval = tokio::time::interval(self.interval);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {},
+                _ = sender.closed() => {
+                    // A new subscriber may have been added while the poller was waking up.
+                    let mut pollers = self.pollers.lock().unwrap();
+                    if sender.receiver_count() == 0 {
+                        pollers.remove(&key);
+                        return;
+                    }
+                    continue;
+                },
+            }
+
+            match fetch().await {
+                Ok(forecast) => {
+                    let is_changed = match &*sender.borrow() {
+                        Update::Forecast(current) => *current != forecast,
+                        _ => true,
+                    };
+                    if is_changed {
+                        let _ = sender.send(Update::Forecast(forecast));
+                    }
+                },
+                Err(status) if matches!(&*sender.borrow(), Update::Pending) => {
+                    self.pollers.lock().unwrap().remove(&key);
+                    let _ = sender.send(Update::Failed(Failure::from(&status)));
+                    return;
+                },
+                Err(status) => {
+                    tracing::warn!(provider = key.provider.as_str(), code = ?status.code(), message = status.message(),
+                                   "Unable to update the subscribed forecast.");
+                },
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn poller_count(&self) -> usize {
+        self.pollers.lock().unwrap().len()
+    }
+}
+
+fn to_stream(receiver: watch::Receiver<Update>) -> impl Stream<Item = Result<WeatherForecast, Status>> {
+    futures::stream::unfold((receiver, true), |(mut receiver, is_first)| async move {
+        if !is_first && receiver.changed().await.is_err() {
+            return None;
+        }
+
+        loop {
+            let update = receiver.borrow_and_update().clone();
+            match update {
+                Update::Pending => receiver.changed().await.ok()?,
+                Update::Forecast(forecast) => return Some((Ok(forecast), (receiver, false))),
+                Update::Failed(failure) => {
+                    let status = Status::with_details(failure.code, failure.message, failure.details);
+                    return Some((Err(status), (receiver, false)));
+                },
+            }
+        }
+    })
+}
+
+/// Subscribes to the forecasts with the global hub, see Hub::subscribe.
+pub fn subscribe<F, Fut>(key: Key, fetch: F) -> impl Stream<Item = Result<WeatherForecast, Status>>
+    where F: Fn() -> Fut + Send + 'static,
+          Fut: Future<Output = Result<WeatherForecast, Status>> + Send + 'static {
+    HUB.subscribe(key, fetch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn get_key() -> Key {
+        let location = Location {
+            name: "Name".to_string(),
+            state: "State".to_string(),
+            country: "Country".to_string(),
+            lon: 1.1,
+            lat: 2.2,
+        };
+
+        Key::new("Stub", &location, "01.01.2000")
+    }
+
+    fn get_forecast(avg_t: f32) -> WeatherForecast {
+        WeatherForecast { dt: 946684800, min_t: avg_t, max_t: avg_t, avg_t, condition: "Fine".to_string() }
+    }
+
+    /// Returns a fetch function that yields the temperatures one by one, repeating the last one,
+    /// and the counter of the requests.
+    fn stub_fetch(temperatures: Vec<f32>)
+        -> (impl Fn() -> futures::future::Ready<Result<WeatherForecast, Status>>, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+
+        let fetch = move || {
+            let index = counter.fetch_add(1, Ordering::SeqCst).min(temperatures.len() - 1);
+            futures::future::ready(Ok(get_forecast(temperatures[index])))
+        };
+
+        (fetch, requests)
+    }
+
+    #[tokio::test]
+    pub async fn test_updates_are_sent_on_change() {
+        let hub = Hub::new(Duration::from_millis(10));
+        let (fetch, _) = stub_fetch(vec![1.0, 1.0, 1.0, 2.0]);
+
+        let mut stream = Box::pin(hub.subscribe(get_key(), fetch));
+
+        assert_eq!(stream.next().await.unwrap().unwrap(), get_forecast(1.0));
+        assert_eq!(stream.next().await.unwrap().unwrap(), get_forecast(2.0));
+    }
+
+    #[tokio::test]
+    pub async fn test_poller_is_shared() {
+        let hub = Hub::new(Duration::from_millis(10));
+        let (fetch, requests) = stub_fetch(vec![1.0]);
+        let (other_fetch, other_requests) = stub_fetch(vec![1.0]);
+
+        let mut first = Box::pin(hub.subscribe(get_key(), fetch));
+        let mut second = Box::pin(hub.subscribe(get_key(), other_fetch));
+
+        assert_eq!(first.next().await.unwrap().unwrap(), get_forecast(1.0));
+        assert_eq!(second.next().await.unwrap().unwrap(), get_forecast(1.0));
+        assert_eq!(hub.poller_count(), 1);
+        assert!(requests.load(Ordering::SeqCst) > 0);
+        assert_eq!(other_requests.load(Ordering::SeqCst), 0);
+
+        drop(first);
+        drop(second);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(hub.poller_count(), 0);
+    }
+
+    #[tokio::test]
+    pub async fn test_first_failure_ends_stream() {
+        let hub = Hub::new(Duration::from_millis(10));
+        let fetch = || futures::future::ready(Err(Status::out_of_range("No forecast")));
+
+        let mut stream = Box::pin(hub.subscribe(get_key(), fetch));
+
+        assert_eq!(stream.next().await.unwrap().err().unwrap().code(), Code::OutOfRange);
+        assert!(stream.next().await.is_none());
+        assert_eq!(hub.poller_count(), 0);
+    }
+}
@@ -0,0 +1,225 @@
+use crate::defs;
+use crate::metrics;
+
+use rand::Rng;
+use std::future::Future;
+use tonic::metadata::MetadataMap;
+use tracing::Instrument;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
+
+static TRACEPARENT_HEADER: &str = "traceparent";
+static TRACESTATE_HEADER: &str = "tracestate";
+static REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    static CURRENT_CONTEXT: TraceContext;
+}
+
+/// Initializes the global logger. The level is taken from LOG_LEVEL (in the RUST_LOG syntax, "info" by default)
+/// and the output format from LOG_FORMAT, which is either "text" (default) or "json".
+/// Closed spans are logged too, so every RPC and upstream request is reported with its duration.
+pub fn init_logging() {
+    let section = defs::CONFIG.general_section();
+
+    let filter = EnvFilter::try_new(section.get(defs::LOG_LEVEL_KEY).unwrap_or("info"))
+        .unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE);
+
+    match section.get(defs::LOG_FORMAT_KEY) {
+        Some("json") => builder.json().init(),
+        _ => builder.init(),
+    }
+}
+
+fn random_hex(bytes: usize) -> String {
+    let mut rng = rand::thread_rng();
+    (0..bytes).map(|_| format!("{:02x}", rng.gen::<u8>())).collect()
+}
+
+fn is_valid_id(id: &str, len: usize) -> bool {
+    id.len() == len && id.chars().all(|c| c.is_ascii_hexdigit()) && id.chars().any(|c| c != '0')
+}
+
+/// W3C trace context of the RPC being handled, together with the request id used in logs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceContext {
+    pub request_id: String,
+    pub trace_id: String,
+    pub parent_id: Option<String>,
+    pub flags: String,
+    pub tracestate: Option<String>,
+}
+
+impl TraceContext {
+    /// Starts a new trace. Used when the client doesn't pass a valid 'traceparent'.
+    pub fn new() -> Self {
+        Self {
+            request_id: random_hex(8),
+            trace_id: random_hex(16),
+            parent_id: None,
+            flags: "01".to_string(),
+            tracestate: None,
+        }
+    }
+
+    /// Parses the 'traceparent' value in the "version-trace_id-parent_id-flags" form.
+    fn parse_traceparent(&mut self, traceparent: &str) -> bool {
+        let parts: Vec<&str> = traceparent.trim().split('-').collect();
+        if parts.len() < 4 || parts[0].len() != 2 || parts[0] == "ff"
+            || !is_valid_id(parts[1], 32) || !is_valid_id(parts[2], 16) || parts[3].len() != 2 {
+            return false;
+        }
+
+        self.trace_id = parts[1].to_lowercase();
+        self.parent_id = Some(parts[2].to_lowercase());
+        self.flags = parts[3].to_lowercase();
+        return true;
+    }
+
+    /// Reads 'traceparent', 'tracestate' and 'x-request-id' from the incoming gRPC metadata.
+    /// Invalid or missing values are replaced with newly generated ones.
+    pub fn from_metadata(metadata: &MetadataMap) -> Self {
+        let mut context = Self::new();
+        let get = |key: &str| metadata.get(key).and_then(|value| value.to_str().ok());
+
+        if let Some(traceparent) = get(TRACEPARENT_HEADER) {
+            if context.parse_traceparent(traceparent) {
+                context.tracestate = get(TRACESTATE_HEADER).map(|value| value.to_string());
+            }
+        }
+
+        if let Some(request_id) = get(REQUEST_ID_HEADER) {
+            context.request_id = request_id.to_string();
+        }
+
+        return context;
+    }
+
+    /// Returns the 'traceparent' value for an outgoing request. Every request gets its own span id
+    /// within the same trace, so the provider sees it as a child of the RPC.
+    pub fn child_traceparent(&self) -> String {
+        format!("00-{}-{}-{}", self.trace_id, random_hex(8), self.flags)
+    }
+}
+
+/// Returns the trace context of the RPC handled by the current task, if any.
+pub fn current_context() -> Option<TraceContext> {
+    CURRENT_CONTEXT.try_with(|context| context.clone()).ok()
+}
+
+/// Adds W3C trace context headers of the current RPC to an outgoing request.
+pub fn inject_trace_context(request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    match current_context() {
+        Some(context) => {
+            let request = request.header(TRACEPARENT_HEADER, context.child_traceparent());
+            match context.tracestate {
+                Some(tracestate) => request.header(TRACESTATE_HEADER, tracestate),
+                None => request,
+            }
+        },
+        None => request,
+    }
+}
+
+/// Makes the trace context and the span of the caller available to the future, which doesn't inherit them
+/// when it runs on another task, e.g. one started by 'tokio::spawn'.
+pub fn propagate<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    let context = current_context();
+
+    async move {
+        match context {
+            Some(context) => CURRENT_CONTEXT.scope(context, future).await,
+            None => future.await,
+        }
+    }.instrument(tracing::Span::current())
+}
+
+/// Runs the RPC handler inside a span that carries the method, request id and trace id.
+/// Handlers can fill in the 'provider' and 'location' fields with 'Span::current().record'.
+/// The trace context is available to the upstream requests made by the handler through 'current_context'.
+pub async fn handle_rpc<T>(method: &str, metadata: &MetadataMap, rpc: impl Future<Output = Result<T, tonic::Status>>)
+    -> Result<T, tonic::Status> {
+    let context = TraceContext::from_metadata(metadata);
+    let span = tracing::info_span!("rpc",
+        method,
+        request_id = %context.request_id,
+        trace_id = %context.trace_id,
+        provider = tracing::field::Empty,
+        location = tracing::field::Empty);
+
+    let result = CURRENT_CONTEXT.scope(context, metrics::track_rpc(method, rpc)).instrument(span.clone()).await;
+
+    span.in_scope(|| match &result {
+        Ok(_) => tracing::info!("RPC completed."),
+        Err(status) => tracing::warn!(code = ?status.code(), message = status.message(), "RPC failed."),
+    });
+
+    return result;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_context_from_traceparent() {
+        let mut metadata = MetadataMap::new();
+        metadata.insert(TRACEPARENT_HEADER, "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".parse().unwrap());
+        metadata.insert(TRACESTATE_HEADER, "vendor=value".parse().unwrap());
+        metadata.insert(REQUEST_ID_HEADER, "request-1".parse().unwrap());
+
+        let context = TraceContext::from_metadata(&metadata);
+        assert_eq!(context.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(context.parent_id, Some("00f067aa0ba902b7".to_string()));
+        assert_eq!(context.flags, "01");
+        assert_eq!(context.tracestate, Some("vendor=value".to_string()));
+        assert_eq!(context.request_id, "request-1");
+
+        let child = context.child_traceparent();
+        assert!(child.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+        assert!(child.ends_with("-01"));
+        assert!(!child.contains("00f067aa0ba902b7"));
+    }
+
+    #[test]
+    pub fn test_invalid_traceparent_starts_new_trace() {
+        let mut metadata = MetadataMap::new();
+        metadata.insert(TRACEPARENT_HEADER, "00-00000000000000000000000000000000-00f067aa0ba902b7-01".parse().unwrap());
+        metadata.insert(TRACESTATE_HEADER, "vendor=value".parse().unwrap());
+
+        let context = TraceContext::from_metadata(&metadata);
+        assert!(is_valid_id(&context.trace_id, 32));
+        assert_eq!(context.parent_id, None);
+        assert_eq!(context.tracestate, None);
+        assert_eq!(context.request_id.len(), 16);
+    }
+
+    #[tokio::test]
+    pub async fn test_context_is_available_in_rpc() {
+        let mut metadata = MetadataMap::new();
+        metadata.insert(REQUEST_ID_HEADER, "request-2".parse().unwrap());
+
+        let result = handle_rpc("TestTraceContext", &metadata, async {
+            Ok(current_context().unwrap().request_id)
+        }).await;
+
+        assert_eq!(result.unwrap(), "request-2");
+        assert!(current_context().is_none());
+    }
+
+    #[tokio::test]
+    pub async fn test_context_is_propagated_to_spawned_task() {
+        let mut metadata = MetadataMap::new();
+        metadata.insert(REQUEST_ID_HEADER, "request-3".parse().unwrap());
+
+        let result = handle_rpc("TestTraceContext", &metadata, async {
+            let task = tokio::spawn(propagate(async { current_context().map(|context| context.request_id) }));
+            Ok(task.await.unwrap())
+        }).await;
+
+        assert_eq!(result.unwrap(), Some("request-3".to_string()));
+    }
+}
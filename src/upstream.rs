@@ -0,0 +1,334 @@
+use crate::defs;
+use crate::metrics;
+use crate::telemetry;
+
+use lazy_static::lazy_static;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tracing::Instrument;
+
+/// Number of consecutive failed requests after which the circuit breaker opens.
+const FAILURE_THRESHOLD: u32 = 5;
+
+/// Time during which requests to a provider with an open circuit breaker are rejected without being sent.
+pub const OPEN_DURATION: Duration = Duration::from_secs(30);
+
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(50);
+
+/// Some providers (e.g. api.weather.gov) reject requests without the User-Agent.
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Default)]
+struct CircuitBreaker {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    /// Set while the single trial request of the half-open state is in flight.
+    trial_started_at: Option<Instant>,
+}
+
+lazy_static! {
+    static ref CLIENT: reqwest::Client = {
+        let timeout = defs::CONFIG.general_section().get(defs::UPSTREAM_TIMEOUT_KEY)
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(10);
+
+        reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .timeout(Duration::from_secs(timeout))
+            .build()
+            .unwrap()
+    };
+    static ref BREAKERS: Mutex<HashMap<String, CircuitBreaker>> = Mutex::new(HashMap::new());
+    static ref MAX_RETRIES: u32 = {
+        return defs::CONFIG.general_section().get(defs::UPSTREAM_MAX_RETRIES_KEY)
+            .and_then(|value| value.parse::<u32>().ok())
+            .unwrap_or(2);
+    };
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// The request could not be completed.
+    Request(reqwest::Error),
+
+    /// The provider failed too many times in a row, so the request was not sent.
+    CircuitOpen(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Request(err) => write!(f, "Unable to make request. {}", err),
+            Error::CircuitOpen(provider) =>
+                write!(f, "{} is temporarily unavailable after several failed requests", provider),
+        }
+    }
+}
+
+impl std::error::Error for Error { }
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Error::Request(err)
+    }
+}
+
+/// Returns true if the request has failed because of the provider, which counts towards its circuit breaker.
+/// Client errors (4xx) other than 429 are not failures, since the result will be the same.
+fn is_failure(result: &Result<reqwest::Response, reqwest::Error>) -> bool {
+    match result {
+        Ok(response) => {
+            response.status().is_server_error() || response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS
+        },
+        Err(_) => true,
+    }
+}
+
+/// Returns true if the failed request makes sense to retry at once. A 429 is not retried, since the retries
+/// would spend the quota and keep the provider limiting; the caller reports its 'Retry-After' instead.
+fn is_retryable(result: &Result<reqwest::Response, reqwest::Error>) -> bool {
+    match result {
+        Ok(response) => response.status().is_server_error(),
+        Err(_) => true,
+    }
+}
+
+fn is_allowed(provider: &str) -> bool {
+    let mut breakers = BREAKERS.lock().unwrap();
+    let breaker = breakers.entry(provider.to_string()).or_default();
+
+    match breaker.opened_at {
+        Some(opened_at) if opened_at.elapsed() < OPEN_DURATION => false,
+        // Only one trial request is let through, the others are rejected until its outcome is known.
+        // A trial that was abandoned without an outcome is replaced after OPEN_DURATION.
+        Some(_) if breaker.trial_started_at.map_or(false, |started_at| started_at.elapsed() < OPEN_DURATION) => false,
+        Some(_) => {
+            breaker.trial_started_at = Some(Instant::now());
+            metrics::set_circuit_breaker_state(provider, metrics::CircuitBreakerState::HalfOpen);
+            true
+        },
+        None => true,
+    }
+}
+
+fn record_outcome(provider: &str, is_success: bool) {
+    let mut breakers = BREAKERS.lock().unwrap();
+    let breaker = breakers.entry(provider.to_string()).or_default();
+    breaker.trial_started_at = None;
+
+    if is_success {
+        breaker.consecutive_failures = 0;
+        breaker.opened_at = None;
+        metrics::set_circuit_breaker_state(provider, metrics::CircuitBreakerState::Closed);
+        return;
+    }
+
+    breaker.consecutive_failures += 1;
+    if breaker.consecutive_failures >= FAILURE_THRESHOLD || breaker.opened_at.is_some() {
+        breaker.opened_at = Some(Instant::now());
+        metrics::set_circuit_breaker_state(provider, metrics::CircuitBreakerState::Open);
+    }
+}
+
+/// Returns the client used for the requests to the providers.
+pub fn client() -> &'static reqwest::Client {
+    &CLIENT
+}
+
+/// Performs a 'GET' request to the provider. Connection errors and 5xx responses are retried
+/// up to UPSTREAM_MAX_RETRIES times with exponential backoff, 429 responses are returned at once. Each provider has its own circuit breaker,
+/// which rejects requests for a while after FAILURE_THRESHOLD consecutive failures.
+/// Responses with other statuses are returned as is and should be handled by the caller.
+pub async fn get(provider: &str, url: reqwest::Url) -> Result<reqwest::Response, Error> {
+    if !is_allowed(provider) {
+        tracing::warn!(provider, "Request rejected by the open circuit breaker.");
+        return Err(Error::CircuitOpen(provider.to_string()));
+    }
+
+    let mut attempt = 0;
+    loop {
+        let span = tracing::info_span!("upstream",
+            provider,
+            host = url.host_str().unwrap_or_default(),
+            attempt,
+            status = tracing::field::Empty);
+
+        let started = Instant::now();
+        let request = telemetry::inject_trace_context(CLIENT.get(url.clone()));
+        // The URL may contain the API key, so it is removed from the error, which is logged and returned to clients.
+        let result = request.send().instrument(span.clone()).await.map_err(reqwest::Error::without_url);
+        metrics::observe_upstream(provider, started, &result);
+
+        match &result {
+            Ok(response) => { span.record("status", &response.status().as_u16()); },
+            Err(err) => span.in_scope(|| tracing::warn!(error = %err, "Upstream request failed.")),
+        }
+
+        let failed = is_failure(&result);
+        if !failed || !is_retryable(&result) || attempt >= *MAX_RETRIES {
+            record_outcome(provider, !failed);
+            return result.map_err(Error::Request);
+        }
+
+        attempt += 1;
+        metrics::record_retry(provider);
+        tokio::time::sleep(RETRY_BASE_DELAY * 2u32.pow(attempt - 1)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+
+    use std::io::Write;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for LogBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn url(server: &MockServer, path: &str) -> reqwest::Url {
+        reqwest::Url::parse(&format!("{}{}", server.base_url(), path)).unwrap()
+    }
+
+    #[tokio::test]
+    pub async fn test_ok_is_not_retried() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/ok");
+            then.status(200);
+        });
+
+        let response = get("TestOk", url(&server, "/ok")).await;
+        assert!(response.is_ok());
+        assert_eq!(mock.hits(), 1);
+    }
+
+    #[tokio::test]
+    pub async fn test_bad_request_is_not_retried() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/bad");
+            then.status(400);
+        });
+
+        let response = get("TestBadRequest", url(&server, "/bad")).await;
+        assert_eq!(response.unwrap().status(), reqwest::StatusCode::BAD_REQUEST);
+        assert_eq!(mock.hits(), 1);
+    }
+
+    #[tokio::test]
+    pub async fn test_server_error_is_retried() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/error");
+            then.status(503);
+        });
+
+        let response = get("TestRetry", url(&server, "/error")).await;
+        assert_eq!(response.unwrap().status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(mock.hits(), *MAX_RETRIES as usize + 1);
+    }
+
+    #[tokio::test]
+    pub async fn test_too_many_requests_is_not_retried() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/limited");
+            then.status(429).header("Retry-After", "60");
+        });
+
+        let response = get("TestTooManyRequests", url(&server, "/limited")).await;
+        assert_eq!(response.unwrap().status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(mock.hits(), 1);
+    }
+
+    #[tokio::test]
+    pub async fn test_trace_context_is_propagated() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/traced").header_exists("traceparent");
+            then.status(200);
+        });
+
+        let metadata = tonic::metadata::MetadataMap::new();
+        let result = telemetry::handle_rpc("TestTracePropagation", &metadata, async {
+            get("TestTrace", url(&server, "/traced")).await.map_err(|err| tonic::Status::internal(err.to_string()))
+        }).await;
+
+        assert!(result.is_ok());
+        assert_eq!(mock.hits(), 1);
+    }
+
+    #[tokio::test]
+    pub async fn test_failed_request_does_not_log_query() {
+        let logs = LogBuffer::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt().with_writer(move || writer.clone()).with_ansi(false).finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        // Nothing listens on the port, so the connection is refused.
+        let url = reqwest::Url::parse("http://127.0.0.1:1/forecast?appid=secret").unwrap();
+        let err = get("TestLogQuery", url).await.unwrap_err();
+
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains("Upstream request failed."));
+        assert!(!logs.contains("appid=secret"));
+        assert!(!err.to_string().contains("appid=secret"));
+    }
+
+    #[test]
+    pub fn test_circuit_breaker_opens() {
+        let provider = "TestCircuitBreaker";
+
+        for _ in 0..FAILURE_THRESHOLD {
+            assert!(is_allowed(provider));
+            record_outcome(provider, false);
+        }
+        assert!(!is_allowed(provider));
+
+        record_outcome(provider, true);
+        assert!(is_allowed(provider));
+    }
+
+    #[test]
+    pub fn test_half_open_allows_single_trial() {
+        let provider = "TestHalfOpen";
+        let expire = || {
+            let mut breakers = BREAKERS.lock().unwrap();
+            let breaker = breakers.get_mut(provider).unwrap();
+            breaker.opened_at = Some(Instant::now() - OPEN_DURATION);
+        };
+
+        for _ in 0..FAILURE_THRESHOLD {
+            record_outcome(provider, false);
+        }
+        expire();
+
+        assert!(is_allowed(provider));
+        assert!(!is_allowed(provider));
+
+        // The failed trial opens the circuit again.
+        record_outcome(provider, false);
+        assert!(!is_allowed(provider));
+
+        expire();
+        assert!(is_allowed(provider));
+        record_outcome(provider, true);
+        assert!(is_allowed(provider));
+        assert!(is_allowed(provider));
+    }
+}
@@ -0,0 +1,7 @@
+//! Messages and the WeatherServerExtensions service generated from proto/weather_server.proto.
+//! Messages shared with the WeatherService are taken from the weather_service_rpc crate.
+
+tonic::include_proto!("weather_server");
+
+/// Encoded file descriptor set of the WeatherServerExtensions, used by the reflection service.
+pub const FILE_DESCRIPTOR_SET: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/weather_server_descriptor.bin"));
@@ -1,12 +1,11 @@
 use super::location_search;
+use super::telemetry;
 
-use super::forecast::ErrorCode;
+use super::error::Error;
 use super::forecast::WeatherForecaster;
-use super::forecast::available_services::AvailableService;
+use super::forecast::registry;
 
-use tonic::{Code, Request, Response, Status};
-
-use std::str::FromStr;
+use tonic::{Request, Response, Status};
 
 use weather_service_rpc::weather_service_server::{WeatherService};
 use weather_service_rpc::{Locations, LocationSearchParams, WeatherProviders, WeatherForecast, WeatherQueryParams};
@@ -16,13 +15,16 @@ use weather_service_rpc::{Locations, LocationSearchParams, WeatherProviders, Wea
 pub struct WeatherServiceImpl;
 #[tonic::async_trait]
 impl WeatherService for WeatherServiceImpl {
-    /// Returns a vector of available weather forecasting services as strings.
-    async fn get_weather_providers(&self, _: Request<()>) -> Result<Response<WeatherProviders>, Status> { 
-        let reply = WeatherProviders{ 
-            providers: AvailableService::iter().map(|service| return format!("{}", service)).collect(), 
-        };
-
-        Ok(Response::new(reply))
+    /// Returns the names of the enabled weather forecasting services.
+    /// Their capabilities are returned by the 'GetProviderDetails' RPC of the WeatherServerExtensions.
+    async fn get_weather_providers(&self, request: Request<()>) -> Result<Response<WeatherProviders>, Status> { 
+        telemetry::handle_rpc("GetWeatherProviders", request.metadata(), async {
+            let reply = WeatherProviders{ 
+                providers: registry::enabled_providers().into_iter().map(|provider| provider.name).collect(), 
+            };
+
+            Ok(Response::new(reply))
+        }).await
     }
 
     /// Accepts an 'LocationSearchParams' which contains query string. 
@@ -30,35 +32,49 @@ impl WeatherService for WeatherServiceImpl {
     /// Returns a 'Locations' message containing a vector of 'Location' structures.
     /// Returns an empty array if no locations were found for the specified query.
     async fn get_locations(&self, search_params: Request<LocationSearchParams>) -> Result<Response<Locations>, Status> {
-        let reply = location_search::perform(search_params.into_inner()).await.or_else(
-            |err| Err(Status::new(Code::Internal, format!("{}", err)))
-        )?;
+        let metadata = search_params.metadata().clone();
 
-        Ok(Response::new(reply))
+        telemetry::handle_rpc("GetLocations", &metadata, async {
+            let reply = location_search::perform(search_params.into_inner()).await.or_else(
+                |err| Err(Status::from(err))
+            )?;
+
+            Ok(Response::new(reply))
+        }).await
     }
     
-    /// Accepts an 'WeatherQueryParams' which contains one of the 'AvailableServices' as string, 
+    /// Accepts an 'WeatherQueryParams' which contains one of the enabled providers as string, 
     /// 'Location' struct and date-string with format mm.dd.yyyy. 
-    /// If an unknown location or invalid date format is passed, an status with code 'InvalidArgument' will be returned.
+    /// If an unknown or disabled provider, no location or invalid date format is passed, an status with code 'InvalidArgument' will be returned.
+    /// For a past date the observed weather is returned if the provider has history.
+    /// A date without forecast or history results in 'OutOfRange'. Provider failures are reported with 'Unauthenticated',
+    /// 'ResourceExhausted', 'Unavailable', 'NotFound' or 'DeadlineExceeded' and google.rpc.ErrorInfo details.
     async fn get_weather(&self, query: Request<WeatherQueryParams>) -> Result<Response<WeatherForecast>, Status> {
-        let params = query.into_inner();
-
-        let service = match AvailableService::from_str(&params.provider) {
-            Ok(matched) => matched,
-            Err(_) => return Err(Status::new(Code::InvalidArgument, "Invalid weather provider passed."))
-        };
-
-        let weather_forecaster = WeatherForecaster::new(service);
-
-        match weather_forecaster.get_weather(params.location.unwrap(), params.date).await {
-            Ok(weather) => Ok(Response::new(weather)),
-            Err(err) => Err(Status::new(
-                    match err.code {
-                        ErrorCode::Internal => Code::Internal,
-                        ErrorCode::InvalidArgument => Code::InvalidArgument
-                    },
-                    format!("{}", err)))
-        }
+        let metadata = query.metadata().clone();
+
+        telemetry::handle_rpc("GetWeather", &metadata, async {
+            let params = query.into_inner();
+
+            let span = tracing::Span::current();
+            span.record("provider", &params.provider.as_str());
+            if let Some(location) = &params.location {
+                span.record("location", &format!("{},{}", location.lat, location.lon).as_str());
+            }
+
+            let weather_forecaster = WeatherForecaster::new(&params.provider).or_else(|err| Err(Status::from(err)))?;
+
+            let location = match params.location {
+                Some(location) => location,
+                None => return Err(Status::from(Error::InvalidArgument {
+                    description: "Location is not specified".to_string()
+                }))
+            };
+
+            match weather_forecaster.get_weather(location, params.date).await {
+                Ok(weather) => Ok(Response::new(weather)),
+                Err(err) => Err(Status::from(err))
+            }
+        }).await
     }
 }
 
@@ -72,7 +88,7 @@ mod integration_tests {
         let providers = WeatherServiceImpl.get_weather_providers(tonic::Request::new(())).await;
         assert!(providers.is_ok());
 
-        let expected: Vec<String> = AvailableService::iter().map(|service| return format!("{}", service)).collect();
+        let expected: Vec<String> = registry::enabled_providers().into_iter().map(|provider| provider.name).collect();
         assert_eq!(providers.unwrap().into_inner().providers, expected)
     }
 
@@ -118,7 +134,7 @@ mod integration_tests {
     pub async fn test_get_weather_ok() {
         let today = chrono::offset::Local::today().format("%m.%d.%Y").to_string();
         let params = WeatherQueryParams { 
-            provider: AvailableService::OpenWeatherMap.to_string(), 
+            provider: "OpenWeatherMap".to_string(), 
             location: Some(get_location().await), 
             date: today };
 
@@ -131,12 +147,28 @@ mod integration_tests {
     pub async fn test_get_weather_wrong_date_format() {
         let today = chrono::offset::Local::today().format("%d.%m.%Y").to_string();
         let params = WeatherQueryParams { 
-            provider: AvailableService::OpenWeatherMap.to_string(), 
+            provider: "OpenWeatherMap".to_string(), 
             location: Some(get_location().await), 
             date: today };
 
         let weather_reply = WeatherServiceImpl.get_weather(tonic::Request::new(params)).await;
 
+        // Swapped day and month still form a valid date if the day is not greater than 12.
+        assert!(weather_reply.is_err());
+        let code = weather_reply.err().unwrap().code();
+        assert!(code == tonic::Code::InvalidArgument || code == tonic::Code::OutOfRange);
+    }
+
+    #[tokio::test]
+    pub async fn test_get_weather_no_location() {
+        let today = chrono::offset::Local::today().format("%m.%d.%Y").to_string();
+        let params = WeatherQueryParams { 
+            provider: "OpenWeatherMap".to_string(), 
+            location: None, 
+            date: today };
+
+        let weather_reply = WeatherServiceImpl.get_weather(tonic::Request::new(params)).await;
+
         assert!(weather_reply.is_err());
         assert_eq!(weather_reply.err().unwrap().code(), tonic::Code::InvalidArgument);
     }
@@ -145,7 +177,7 @@ mod integration_tests {
     pub async fn test_get_weather_wrong_location() {
         let today = chrono::offset::Local::today().format("%m.%d.%Y").to_string();
         let params = WeatherQueryParams { 
-            provider: AvailableService::OpenWeatherMap.to_string(), 
+            provider: "OpenWeatherMap".to_string(), 
             location: Some(Location {
                 name: "Name".to_string(),
                 state: "State".to_string(),